@@ -0,0 +1,293 @@
+use std::io::Write;
+
+use anyhow::Result;
+use clap::ValueEnum;
+
+/// Shells `notehub completions` can write a script for.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+/// One command of the tree: the subcommand names leading to it, what can follow it, and its
+/// flags.
+struct Node {
+    path: Vec<String>,
+    subcommands: Vec<(String, String)>,
+    flags: Vec<Flag>,
+}
+
+struct Flag {
+    long: String,
+    short: Option<char>,
+    help: String,
+    takes_value: bool,
+    /// Accepted values, for flags backed by a value enum.
+    values: Vec<String>,
+}
+
+/// Writes a completion script for `shell` covering every subcommand and long flag of `cmd`.
+pub fn generate(shell: Shell, cmd: &mut clap::Command, out: &mut impl Write) -> Result<()> {
+    cmd.build();
+    let bin = cmd.get_name().to_string();
+    let mut nodes = Vec::new();
+    collect(cmd, Vec::new(), &mut nodes);
+    let script = match shell {
+        Shell::Bash => bash(&bin, &nodes),
+        Shell::Zsh => format!(
+            "#compdef {bin}\n\nautoload -U +X bashcompinit && bashcompinit\n\n{}",
+            bash(&bin, &nodes)
+        ),
+        Shell::Fish => fish(&bin, &nodes),
+        Shell::Powershell => powershell(&bin, &nodes),
+    };
+    out.write_all(script.as_bytes())?;
+    out.flush()?;
+    Ok(())
+}
+
+fn collect(cmd: &clap::Command, path: Vec<String>, nodes: &mut Vec<Node>) {
+    let subcommands = cmd
+        .get_subcommands()
+        .filter(|sub| !sub.is_hide_set())
+        .map(|sub| (sub.get_name().to_string(), first_line(sub.get_about())))
+        .collect();
+    let flags = cmd
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set())
+        .filter_map(|arg| {
+            Some(Flag {
+                long: arg.get_long()?.to_string(),
+                short: arg.get_short(),
+                help: first_line(arg.get_help()),
+                takes_value: arg.get_action().takes_values(),
+                values: arg
+                    .get_possible_values()
+                    .iter()
+                    .filter(|value| !value.is_hide_set())
+                    .map(|value| value.get_name().to_string())
+                    .collect(),
+            })
+        })
+        .collect();
+    nodes.push(Node {
+        path: path.clone(),
+        subcommands,
+        flags,
+    });
+    for sub in cmd.get_subcommands().filter(|sub| !sub.is_hide_set()) {
+        let mut sub_path = path.clone();
+        sub_path.push(sub.get_name().to_string());
+        collect(sub, sub_path, nodes);
+    }
+}
+
+fn first_line(text: Option<&clap::builder::StyledStr>) -> String {
+    text.map(|text| {
+        text.to_string()
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    })
+    .unwrap_or_default()
+}
+
+fn key(bin: &str, path: &[String], separator: &str) -> String {
+    std::iter::once(bin)
+        .chain(path.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn bash(bin: &str, nodes: &[Node]) -> String {
+    let function = format!("_{}", bin.replace('-', "_"));
+    let subcommand_keys = nodes
+        .iter()
+        .filter(|node| !node.path.is_empty())
+        .map(|node| key(bin, &node.path, ":"))
+        .collect::<Vec<_>>()
+        .join("|");
+    let mut value_cases = String::new();
+    let mut word_cases = String::new();
+    for node in nodes {
+        let node_key = key(bin, &node.path, ":");
+        for flag in node.flags.iter().filter(|flag| flag.takes_value) {
+            let reply = if flag.values.is_empty() {
+                "compgen -f -- \"$cur\"".to_string()
+            } else {
+                format!("compgen -W \"{}\" -- \"$cur\"", flag.values.join(" "))
+            };
+            value_cases.push_str(&format!(
+                "        {node_key}:--{})\n            COMPREPLY=($({reply}))\n            return ;;\n",
+                flag.long
+            ));
+        }
+        let words = node
+            .subcommands
+            .iter()
+            .map(|(name, _)| name.clone())
+            .chain(node.flags.iter().map(|flag| format!("--{}", flag.long)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        word_cases.push_str(&format!(
+            "        {node_key})\n            opts=\"{words}\" ;;\n"
+        ));
+    }
+    format!(
+        "{function}() {{
+    local cur prev path word opts
+    cur=\"${{COMP_WORDS[COMP_CWORD]}}\"
+    prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"
+    path=\"{bin}\"
+    for word in \"${{COMP_WORDS[@]:1:COMP_CWORD-1}}\"; do
+        case \"$path:$word\" in
+            {subcommand_keys})
+                path=\"$path:$word\" ;;
+        esac
+    done
+    case \"$path:$prev\" in
+{value_cases}    esac
+    case \"$path\" in
+{word_cases}    esac
+    COMPREPLY=($(compgen -W \"$opts\" -- \"$cur\"))
+}}
+
+complete -F {function} {bin}
+"
+    )
+}
+
+fn fish(bin: &str, nodes: &[Node]) -> String {
+    let mut script = String::new();
+    for node in nodes {
+        let seen = node
+            .path
+            .iter()
+            .map(|name| format!("__fish_seen_subcommand_from {name}"))
+            .collect::<Vec<_>>();
+        if !node.subcommands.is_empty() {
+            let children = node
+                .subcommands
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+            let condition = if node.path.is_empty() {
+                "__fish_use_subcommand".to_string()
+            } else {
+                format!(
+                    "{}; and not __fish_seen_subcommand_from {children}",
+                    seen.join("; and ")
+                )
+            };
+            for (name, about) in &node.subcommands {
+                script.push_str(&format!(
+                    "complete -c {bin} -f -n '{condition}' -a '{name}' -d '{}'\n",
+                    fish_escape(about)
+                ));
+            }
+        }
+        for flag in &node.flags {
+            let mut line = format!("complete -c {bin}");
+            if !node.path.is_empty() {
+                line.push_str(&format!(" -n '{}'", seen.join("; and ")));
+            }
+            line.push_str(&format!(" -l {}", flag.long));
+            if let Some(short) = flag.short {
+                line.push_str(&format!(" -s {short}"));
+            }
+            if !flag.values.is_empty() {
+                line.push_str(&format!(" -x -a '{}'", flag.values.join(" ")));
+            } else if flag.takes_value {
+                line.push_str(" -r");
+            }
+            line.push_str(&format!(" -d '{}'\n", fish_escape(&flag.help)));
+            script.push_str(&line);
+        }
+    }
+    script
+}
+
+fn fish_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn powershell(bin: &str, nodes: &[Node]) -> String {
+    let mut cases = String::new();
+    for node in nodes {
+        cases.push_str(&format!("        '{}' {{\n", key(bin, &node.path, ";")));
+        for (name, about) in &node.subcommands {
+            cases.push_str(&format!(
+                "            [CompletionResult]::new('{name}', '{name}', [CompletionResultType]::ParameterValue, '{}')\n",
+                powershell_escape(about)
+            ));
+        }
+        for flag in &node.flags {
+            cases.push_str(&format!(
+                "            [CompletionResult]::new('--{0}', '{0}', [CompletionResultType]::ParameterName, '{1}')\n",
+                flag.long,
+                powershell_escape(&flag.help)
+            ));
+        }
+        cases.push_str("            break\n        }\n");
+    }
+    format!(
+        "using namespace System.Management.Automation
+using namespace System.Management.Automation.Language
+
+Register-ArgumentCompleter -Native -CommandName '{bin}' -ScriptBlock {{
+    param($wordToComplete, $commandAst, $cursorPosition)
+
+    $commandElements = $commandAst.CommandElements
+    $command = @(
+        '{bin}'
+        for ($i = 1; $i -lt $commandElements.Count; $i++) {{
+            $element = $commandElements[$i]
+            if ($element -isnot [StringConstantExpressionAst] -or
+                $element.StringConstantType -ne [StringConstantType]::BareWord -or
+                $element.Value.StartsWith('-') -or
+                $element.Value -eq $wordToComplete) {{
+                break
+            }}
+            $element.Value
+        }}) -join ';'
+
+    $completions = @(switch ($command) {{
+{cases}    }})
+
+    $completions.Where{{ $_.CompletionText -like \"$wordToComplete*\" }} |
+        Sort-Object -Property ListItemText
+}}
+"
+    )
+}
+
+fn powershell_escape(text: &str) -> String {
+    let text = if text.is_empty() { " " } else { text };
+    text.replace('\'', "''")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fish_escape_escapes_backslashes_and_quotes() {
+        assert_eq!(fish_escape("plain"), "plain");
+        assert_eq!(fish_escape("it's"), "it\\'s");
+        assert_eq!(fish_escape("a\\b"), "a\\\\b");
+        assert_eq!(fish_escape("\\'"), "\\\\\\'");
+    }
+
+    #[test]
+    fn powershell_escape_doubles_quotes_and_fills_empty_help() {
+        assert_eq!(powershell_escape("plain"), "plain");
+        assert_eq!(powershell_escape("it's"), "it''s");
+        assert_eq!(powershell_escape("a\\b"), "a\\b");
+        assert_eq!(powershell_escape(""), " ");
+    }
+}
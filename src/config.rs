@@ -1,46 +1,138 @@
-use std::{fs, path::PathBuf};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
-use anyhow::{Context, Result, anyhow, ensure};
+use anyhow::{Context, Result, anyhow, bail, ensure};
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 
 const CONFIG_FILE_NAME: &str = "config.toml";
 
+/// Stored as `github_token` when the real token lives in the OS keyring.
+pub const KEYRING_TOKEN: &str = "keyring";
+const KEYRING_SERVICE: &str = "NoteHub";
+const KEYRING_ACCOUNT: &str = "github_token";
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Config {
     pub github_token: Option<String>,
     #[serde(default)]
     pub repos: Vec<String>,
     pub active_repo: Option<String>,
+    /// Login of the account the token last authenticated as (recorded by `whoami`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub github_login: Option<String>,
+    /// Bodies larger than this many bytes are truncated when cached.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_body_size: Option<usize>,
+    /// API root of a GitHub Enterprise Server, e.g. `https://ghe.example.com/api/v3`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub github_base_url: Option<String>,
+    /// Take `GITHUB_TOKEN` from the environment over `github_token`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub prefer_env_token: bool,
+    /// Per-repository defaults, written as `[overrides."owner/name"]` tables.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub overrides: BTreeMap<String, RepoOverride>,
+    /// Upgrades applied in memory because the file on disk uses an older layout.
+    #[serde(skip)]
+    pub pending_migrations: Vec<String>,
+}
+
+/// Defaults for one repository that replace the global ones; unset fields fall back to them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RepoOverride {
+    /// Cache comments on every `sync` of the repository, as if `--with-comments` were given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync_with_comments: Option<bool>,
+    /// State `issue list` shows without `--state`: `open`, `closed`, or `all`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub list_state: Option<String>,
 }
 
 impl Config {
-    pub fn load() -> Result<(Self, PathBuf)> {
-        let (path, exists) = config_path()?;
-        if !exists {
+    /// Loads the config at `path`, or at the default location; a missing file yields defaults.
+    pub fn load(path: Option<&Path>) -> Result<(Self, PathBuf)> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => config_path()?,
+        };
+        if !path.exists() {
             return Ok((Self::default(), path));
         }
 
-        let raw_text = fs::read_to_string(&path)
+        let (mut cfg, changes) = Self::read_upgraded(&path)?;
+        cfg.pending_migrations = changes;
+        Ok((cfg, path))
+    }
+
+    /// Rewrites an old-format config file in the current layout, keeping the original as
+    /// `config.toml.bak`. Returns the changes made; empty if the file was already current.
+    pub fn migrate_file(path: &Path) -> Result<Vec<String>> {
+        let (cfg, changes) = Self::read_upgraded(path)?;
+        if changes.is_empty() {
+            return Ok(changes);
+        }
+        let backup = backup_path(path);
+        fs::copy(path, &backup).with_context(|| {
+            format!(
+                "failed to back up {} to {}",
+                path.display(),
+                backup.display()
+            )
+        })?;
+        cfg.save(path)?;
+        Ok(changes)
+    }
+
+    /// Parses the config at `path`, upgrading any legacy keys on the way.
+    fn read_upgraded(path: &Path) -> Result<(Self, Vec<String>)> {
+        let raw_text = fs::read_to_string(path)
             .with_context(|| format!("failed to read config at {}", path.display()))?;
-        let mut cfg: Self = toml::from_str(&raw_text)
+        let mut table: toml::Table = toml::from_str(&raw_text)
             .with_context(|| format!("failed to parse config at {}", path.display()))?;
+        let mut changes = upgrade_legacy_keys(&mut table);
+        let mut cfg: Self = toml::Value::Table(table)
+            .try_into()
+            .with_context(|| format!("failed to parse config at {}", path.display()))?;
+        if cfg.lowercase_repos() {
+            changes.push("lowercased repository names".to_string());
+        }
         cfg.deduplicate_repos();
-        Ok((cfg, path))
+        cfg.normalize_overrides()
+            .with_context(|| format!("invalid override in config at {}", path.display()))?;
+        Ok((cfg, changes))
     }
 
-    pub fn save(&self, path: &PathBuf) -> Result<()> {
+    /// Writes the config through a temporary file so a crash never leaves it half-written.
+    pub fn save(&self, path: &Path) -> Result<()> {
         if let Some(dir) = path.parent() {
             fs::create_dir_all(dir)
                 .with_context(|| format!("failed to create {}", dir.display()))?;
         }
 
         let raw = toml::to_string_pretty(self).context("failed to encode configuration")?;
-        fs::write(path, raw)
+        let tmp = path.with_extension("toml.tmp");
+        fs::write(&tmp, raw)
+            .with_context(|| format!("failed to write config to {}", tmp.display()))?;
+        fs::rename(&tmp, path)
             .with_context(|| format!("failed to write config to {}", path.display()))?;
         Ok(())
     }
 
+    /// The config as TOML for display, with the token masked to its last four characters.
+    pub fn redacted_toml(&self) -> Result<String> {
+        let mut table = toml::Table::try_from(self).context("failed to encode configuration")?;
+        if let Some(toml::Value::String(token)) = table.get_mut("github_token")
+            && token != KEYRING_TOKEN
+        {
+            *token = redact_token(token);
+        }
+        toml::to_string_pretty(&table).context("failed to encode configuration")
+    }
+
     pub fn add_repo(&mut self, repo: &str) -> Result<(String, bool)> {
         let normalized = Self::normalize_repo(repo)?;
         if !self.repos.contains(&normalized) {
@@ -58,6 +150,7 @@ impl Config {
         let normalized = Self::normalize_repo(repo)?;
         if let Some(pos) = self.repos.iter().position(|r| r == &normalized) {
             self.repos.remove(pos);
+            self.overrides.remove(&normalized);
             if self.active_repo.as_ref() == Some(&normalized) {
                 self.active_repo = self.repos.first().cloned();
             }
@@ -67,6 +160,29 @@ impl Config {
         }
     }
 
+    /// Renames a configured repository in place, keeping it active if it was.
+    pub fn rename_repo(&mut self, old: &str, new: &str) -> Result<(String, String)> {
+        let old = Self::normalize_repo(old)?;
+        let new = Self::normalize_repo(new)?;
+        ensure!(
+            !self.repos.contains(&new),
+            "repository {new} is already configured"
+        );
+        let pos = self
+            .repos
+            .iter()
+            .position(|r| r == &old)
+            .with_context(|| format!("repository {old} is not configured"))?;
+        self.repos[pos] = new.clone();
+        if let Some(repo_override) = self.overrides.remove(&old) {
+            self.overrides.insert(new.clone(), repo_override);
+        }
+        if self.active_repo.as_ref() == Some(&old) {
+            self.active_repo = Some(new.clone());
+        }
+        Ok((old, new))
+    }
+
     pub fn set_active_repo(&mut self, repo: &str) -> Result<String> {
         let normalized = Self::normalize_repo(repo)?;
         ensure!(
@@ -89,34 +205,136 @@ impl Config {
         self.active_repo = self.repos.first().cloned();
     }
 
+    /// Checks `owner/name` and returns it lowercased: GitHub resolves repositories
+    /// case-insensitively, so `Owner/Repo` and `owner/repo` share one cache entry.
     pub fn normalize_repo(repo: &str) -> Result<String> {
-        let trimmed = repo.trim().trim_matches('/');
+        let trimmed = strip_repo_url(repo);
         ensure!(
             !trimmed.is_empty(),
             "repository must be in the form owner/name"
         );
+        ensure!(
+            !trimmed.contains(':'),
+            "repository must be owner/name or a github.com URL, not {trimmed}"
+        );
         let mut parts = trimmed.split('/');
         let owner = parts
             .next()
+            .filter(|owner| !owner.is_empty())
             .ok_or_else(|| anyhow!("repository must include an owner"))?;
         let name = parts
             .next()
+            .filter(|name| !name.is_empty())
             .ok_or_else(|| anyhow!("repository must include a name"))?;
         ensure!(
-            parts.next().is_none(),
+            parts.next().is_none() && !trimmed.contains(char::is_whitespace),
             "repository must be in the form owner/name"
         );
-        Ok(format!("{owner}/{name}"))
+        Ok(format!("{owner}/{name}").to_ascii_lowercase())
+    }
+
+    /// Checks a GitHub Enterprise API URL, trimming trailing slashes and adding the `/api/v3`
+    /// prefix when only the host is given. github.com itself maps to its API host.
+    pub fn normalize_base_url(url: &str) -> Result<String> {
+        let trimmed = url.trim().trim_end_matches('/');
+        let without_scheme = trimmed
+            .strip_prefix("https://")
+            .or_else(|| trimmed.strip_prefix("http://"))
+            .ok_or_else(|| anyhow!("base URL {trimmed} must start with https:// or http://"))?;
+        let (host, path) = without_scheme
+            .split_once('/')
+            .unwrap_or((without_scheme, ""));
+        ensure!(
+            !host.is_empty() && !host.contains(|c: char| c.is_whitespace() || "?#@".contains(c)),
+            "base URL {trimmed} does not have a valid host"
+        );
+        ensure!(
+            !path.contains(|c: char| c.is_whitespace() || "?#".contains(c)),
+            "base URL {trimmed} must not contain spaces, a query or a fragment"
+        );
+        if matches!(
+            host.to_ascii_lowercase().as_str(),
+            "github.com" | "www.github.com"
+        ) {
+            Ok(crate::github::API_BASE_URL.to_string())
+        } else if path.is_empty() && host != "api.github.com" {
+            Ok(format!("{trimmed}/api/v3"))
+        } else {
+            Ok(trimmed.to_string())
+        }
+    }
+
+    /// Extracts `owner/name` from a GitHub repository, issue, pull request, or discussion URL.
+    pub fn repo_from_url(url: &str) -> Result<String> {
+        let trimmed = url.trim();
+        let without_scheme = trimmed
+            .strip_prefix("https://")
+            .or_else(|| trimmed.strip_prefix("http://"))
+            .ok_or_else(|| anyhow!("{trimmed} is not an http(s) URL"))?;
+        let path = without_scheme.split(['?', '#']).next().unwrap_or_default();
+        let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+        let _host = segments
+            .next()
+            .ok_or_else(|| anyhow!("{trimmed} does not include a host"))?;
+        let owner = segments.next();
+        let name = segments.next().map(|name| name.trim_end_matches(".git"));
+        match (owner, name) {
+            (Some(owner), Some(name)) if !name.is_empty() => {
+                Self::normalize_repo(&format!("{owner}/{name}"))
+            }
+            _ => Err(anyhow!(
+                "{trimmed} does not contain an owner/name repository"
+            )),
+        }
     }
 
     pub fn repos(&self) -> &[String] {
         &self.repos
     }
 
+    pub fn token_in_keyring(&self) -> bool {
+        self.github_token.as_deref() == Some(KEYRING_TOKEN)
+    }
+
     pub fn active_repo(&self) -> Option<&String> {
         self.active_repo.as_ref()
     }
 
+    /// The overrides configured for `repo`, if any.
+    pub fn repo_override(&self, repo: &str) -> Option<&RepoOverride> {
+        self.overrides.get(repo)
+    }
+
+    /// Normalizes override keys like configured repositories and checks their values.
+    fn normalize_overrides(&mut self) -> Result<()> {
+        let mut normalized = BTreeMap::new();
+        for (repo, repo_override) in std::mem::take(&mut self.overrides) {
+            let repo = Self::normalize_repo(&repo)?;
+            if let Some(state) = &repo_override.list_state {
+                ensure!(
+                    matches!(state.as_str(), "open" | "closed" | "all"),
+                    "list_state for {repo} must be `open`, `closed`, or `all`, not `{state}`"
+                );
+            }
+            normalized.insert(repo, repo_override);
+        }
+        self.overrides = normalized;
+        Ok(())
+    }
+
+    /// Lowercases repository names written by versions that kept their casing; returns
+    /// whether any changed.
+    fn lowercase_repos(&mut self) -> bool {
+        let mut changed = false;
+        for repo in self.repos.iter_mut().chain(self.active_repo.as_mut()) {
+            if repo.chars().any(|c| c.is_ascii_uppercase()) {
+                *repo = repo.to_ascii_lowercase();
+                changed = true;
+            }
+        }
+        changed
+    }
+
     fn deduplicate_repos(&mut self) {
         let mut unique = Vec::new();
         for repo in &self.repos {
@@ -129,9 +347,286 @@ impl Config {
     }
 }
 
-fn config_path() -> Result<(PathBuf, bool)> {
+/// Moves keys from the original single-repository layout (`token`, `repo`) to their current
+/// names, returning a description of each change.
+fn upgrade_legacy_keys(table: &mut toml::Table) -> Vec<String> {
+    let mut changes = Vec::new();
+    if let Some(token) = table.remove("token") {
+        if table.contains_key("github_token") {
+            changes.push("dropped `token` (superseded by `github_token`)".to_string());
+        } else {
+            table.insert("github_token".to_string(), token);
+            changes.push("renamed `token` to `github_token`".to_string());
+        }
+    }
+    if let Some(repo) = table.remove("repo") {
+        if let Some(name) = repo.as_str() {
+            let repos = table
+                .entry("repos")
+                .or_insert_with(|| toml::Value::Array(Vec::new()));
+            if let Some(repos) = repos.as_array_mut()
+                && !repos.contains(&repo)
+            {
+                repos.push(repo.clone());
+            }
+            if !table.contains_key("active_repo") {
+                table.insert("active_repo".to_string(), repo.clone());
+            }
+            changes.push(format!("moved `repo = \"{name}\"` into `repos`"));
+        } else {
+            changes.push("dropped non-string `repo`".to_string());
+        }
+    }
+    changes
+}
+
+/// Saves the token in the OS keyring: the login keychain via `security` on macOS, the Secret
+/// Service via `secret-tool` (libsecret) elsewhere. The token is passed on stdin, never argv.
+pub fn keyring_store(token: &str) -> Result<()> {
+    ensure!(
+        !token.is_empty() && !token.contains(|c: char| c.is_whitespace() || c == '"'),
+        "token contains whitespace or quotes; refusing to store it"
+    );
+    if cfg!(target_os = "macos") {
+        let command = format!(
+            "add-generic-password -U -s {KEYRING_SERVICE} -a {KEYRING_ACCOUNT} -w \"{token}\"\n"
+        );
+        run_keyring("security", &["-i"], Some(&command))?;
+    } else {
+        run_keyring(
+            "secret-tool",
+            &[
+                "store",
+                "--label=NoteHub GitHub token",
+                "service",
+                KEYRING_SERVICE,
+                "account",
+                KEYRING_ACCOUNT,
+            ],
+            Some(token),
+        )?;
+    }
+    Ok(())
+}
+
+pub fn keyring_load() -> Result<String> {
+    let token = if cfg!(target_os = "macos") {
+        run_keyring(
+            "security",
+            &[
+                "find-generic-password",
+                "-s",
+                KEYRING_SERVICE,
+                "-a",
+                KEYRING_ACCOUNT,
+                "-w",
+            ],
+            None,
+        )?
+    } else {
+        run_keyring(
+            "secret-tool",
+            &[
+                "lookup",
+                "service",
+                KEYRING_SERVICE,
+                "account",
+                KEYRING_ACCOUNT,
+            ],
+            None,
+        )?
+    };
+    let token = token.trim().to_string();
+    ensure!(
+        !token.is_empty(),
+        "no GitHub token found in the OS keyring; run `notehub init --token ... --use-keyring`"
+    );
+    Ok(token)
+}
+
+pub fn keyring_delete() -> Result<()> {
+    if cfg!(target_os = "macos") {
+        run_keyring(
+            "security",
+            &[
+                "delete-generic-password",
+                "-s",
+                KEYRING_SERVICE,
+                "-a",
+                KEYRING_ACCOUNT,
+            ],
+            None,
+        )?;
+    } else {
+        run_keyring(
+            "secret-tool",
+            &[
+                "clear",
+                "service",
+                KEYRING_SERVICE,
+                "account",
+                KEYRING_ACCOUNT,
+            ],
+            None,
+        )?;
+    }
+    Ok(())
+}
+
+/// Runs a keyring helper, feeding it `input` and returning its stdout.
+fn run_keyring(program: &str, args: &[&str], input: Option<&str>) -> Result<String> {
+    if cfg!(windows) {
+        bail!(
+            "no OS keyring backend is available on this platform; keep the token in the config or GITHUB_TOKEN"
+        );
+    }
+    let mut child = match Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) if err.kind() == ErrorKind::NotFound => bail!(
+            "no OS keyring backend is available (`{program}` not found); keep the token in the config or GITHUB_TOKEN"
+        ),
+        Err(err) => return Err(err).with_context(|| format!("failed to run `{program}`")),
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(input.unwrap_or_default().as_bytes())
+            .with_context(|| format!("failed to write to `{program}`"))?;
+    }
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("failed to run `{program}`"))?;
+    ensure!(
+        output.status.success(),
+        "`{program} {}` failed: {}",
+        args[0],
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+/// `***` followed by the last four characters, or just `***` for tokens too short to hide.
+fn redact_token(token: &str) -> String {
+    let chars = token.chars().collect::<Vec<_>>();
+    if chars.len() <= 8 {
+        return "***".to_string();
+    }
+    let tail = chars[chars.len() - 4..].iter().collect::<String>();
+    format!("***{tail}")
+}
+
+/// Reduces a pasted GitHub URL (`https://github.com/owner/name`, `git@github.com:owner/name.git`)
+/// or `owner/name.git` to `owner/name`; anything else is returned trimmed.
+pub fn strip_repo_url(repo: &str) -> &str {
+    let trimmed = repo.trim();
+    let path = ["https://", "http://", "ssh://git@", "git@"]
+        .iter()
+        .find_map(|scheme| trimmed.strip_prefix(scheme))
+        .and_then(|rest| {
+            rest.strip_prefix("github.com/")
+                .or_else(|| rest.strip_prefix("github.com:"))
+        })
+        .unwrap_or(trimmed)
+        .trim_matches('/');
+    path.strip_suffix(".git").unwrap_or(path)
+}
+
+pub fn config_path() -> Result<PathBuf> {
     let dirs = ProjectDirs::from("com", "LexicalMathical", "NoteHub")
         .ok_or_else(|| anyhow!("unable to determine config directory"))?;
-    let path = dirs.config_dir().join(CONFIG_FILE_NAME);
-    Ok((path.clone(), path.exists()))
+    Ok(dirs.config_dir().join(CONFIG_FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_repo_lowercases_so_casings_share_one_cache_entry() {
+        assert_eq!(Config::normalize_repo("Owner/Repo").unwrap(), "owner/repo");
+        assert_eq!(
+            Config::normalize_repo("Owner/Repo").unwrap(),
+            Config::normalize_repo("owner/repo").unwrap()
+        );
+    }
+
+    #[test]
+    fn strip_repo_url_reduces_github_urls_to_owner_and_name() {
+        assert_eq!(strip_repo_url("https://github.com/o/n"), "o/n");
+        assert_eq!(strip_repo_url("https://github.com/o/n/"), "o/n");
+        assert_eq!(strip_repo_url("git@github.com:o/n.git"), "o/n");
+        assert_eq!(strip_repo_url("ssh://git@github.com/o/n.git"), "o/n");
+        assert_eq!(strip_repo_url("o/n.git"), "o/n");
+        assert_eq!(strip_repo_url("  o/n  "), "o/n");
+    }
+
+    #[test]
+    fn normalize_repo_accepts_urls_and_git_suffixes() {
+        for input in [
+            "https://github.com/O/N",
+            "git@github.com:o/n.git",
+            "o/n.git",
+            "o/n",
+        ] {
+            assert_eq!(Config::normalize_repo(input).unwrap(), "o/n", "{input}");
+        }
+    }
+
+    #[test]
+    fn normalize_repo_rejects_other_shapes() {
+        for input in [
+            "",
+            "o",
+            "o/",
+            "/n",
+            "o/n/extra",
+            "o /n",
+            "https://gitlab.com/o/n",
+            "git@gitlab.com:o/n.git",
+        ] {
+            assert!(Config::normalize_repo(input).is_err(), "{input:?}");
+        }
+    }
+
+    #[test]
+    fn normalize_base_url_adds_the_enterprise_prefix() {
+        for (input, expected) in [
+            ("https://ghe.example.com", "https://ghe.example.com/api/v3"),
+            ("https://ghe.example.com/", "https://ghe.example.com/api/v3"),
+            (
+                "https://ghe.example.com/api/v3/",
+                "https://ghe.example.com/api/v3",
+            ),
+            ("https://api.github.com", "https://api.github.com"),
+        ] {
+            assert_eq!(Config::normalize_base_url(input).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn normalize_base_url_maps_github_com_to_its_api() {
+        for input in [
+            "https://github.com",
+            "https://github.com/",
+            "https://GitHub.com/api/v3",
+            "http://www.github.com",
+        ] {
+            assert_eq!(
+                Config::normalize_base_url(input).unwrap(),
+                crate::github::API_BASE_URL,
+                "{input}"
+            );
+        }
+    }
 }
@@ -0,0 +1,144 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+
+use crate::storage::{self, IssueFilter, Storage, StoredIssueDetail, StoredNote};
+
+/// Longest title slug used in an exported file name.
+const MAX_SLUG_LEN: usize = 50;
+
+/// Writes every cached issue of `repo` into `dir` as Markdown: one file per issue with its
+/// notes as blockquotes, plus an `index.md` linking them all. Returns the number of issues.
+pub fn export_markdown(storage: &Storage, repo: &str, dir: &Path) -> Result<usize> {
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    let mut issues = storage.list_issues(repo, &IssueFilter::default())?;
+    issues.sort_by_key(|issue| issue.number);
+
+    let mut index = format!("# {repo}\n\n");
+    if issues.is_empty() {
+        index.push_str("(no cached issues)\n");
+    }
+    for summary in &issues {
+        let number = summary.number as u64;
+        let Some(issue) = storage.get_issue(repo, number)? else {
+            continue;
+        };
+        let notes = storage.list_notes(repo, number)?;
+        let file_name = issue_file_name(number, &issue.title);
+        let path = dir.join(&file_name);
+        fs::write(&path, render_issue(repo, &issue, &notes))
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        let state = issue.state.as_deref().unwrap_or("unknown");
+        index.push_str(&format!(
+            "- [#{number} {}]({file_name}) ({state})\n",
+            escape_link_text(&issue.title)
+        ));
+    }
+
+    let index_path = dir.join("index.md");
+    fs::write(&index_path, index)
+        .with_context(|| format!("failed to write {}", index_path.display()))?;
+    Ok(issues.len())
+}
+
+/// Writes the cached issues and notes of `repos` to `out` as one JSON document:
+/// `{"schema_version", "exported_at", "repos": [{"repo", "issues": [...]}]}`. Issues are
+/// serialized as they are read, so memory use does not grow with the cache. Returns the
+/// number of issues written.
+pub fn export_json(storage: &Storage, repos: &[String], mut out: impl Write) -> Result<usize> {
+    write!(
+        out,
+        "{{\n  \"schema_version\": {},\n  \"exported_at\": {},\n  \"repos\": [",
+        storage::SCHEMA_VERSION,
+        serde_json::to_string(&Utc::now().to_rfc3339())?
+    )?;
+    let mut total = 0;
+    for (repo_index, repo) in repos.iter().enumerate() {
+        let separator = if repo_index == 0 { "" } else { "," };
+        write!(
+            out,
+            "{separator}\n    {{\"repo\": {}, \"issues\": [",
+            serde_json::to_string(repo)?
+        )?;
+        let mut count = 0;
+        storage.for_each_archived_issue(repo, |issue| {
+            let separator = if count == 0 { "" } else { "," };
+            write!(out, "{separator}\n      ")?;
+            serde_json::to_writer(&mut out, &issue).context("failed to encode issue")?;
+            count += 1;
+            Ok(())
+        })?;
+        write!(out, "{}]}}", if count == 0 { "" } else { "\n    " })?;
+        total += count;
+    }
+    writeln!(out, "{}]\n}}", if repos.is_empty() { "" } else { "\n  " })?;
+    out.flush()?;
+    Ok(total)
+}
+
+fn render_issue(repo: &str, issue: &StoredIssueDetail, notes: &[StoredNote]) -> String {
+    let labels = if issue.labels.is_empty() {
+        "none".to_string()
+    } else {
+        issue.labels.join(", ")
+    };
+    let mut out = format!(
+        "# #{} {}\n\n- Repository: {repo}\n- State: {}\n- Labels: {labels}\n- Updated: {}\n",
+        issue.number,
+        issue.title,
+        issue.state.as_deref().unwrap_or("unknown"),
+        issue.updated_at.format("%Y-%m-%d %H:%M")
+    );
+    match issue.body.as_deref().map(str::trim) {
+        Some(body) if !body.is_empty() => out.push_str(&format!("\n{body}\n")),
+        _ => out.push_str("\n_No description._\n"),
+    }
+    if !notes.is_empty() {
+        out.push_str("\n## Notes\n");
+        for note in notes {
+            out.push('\n');
+            for line in note.body.trim_end().lines() {
+                if line.is_empty() {
+                    out.push_str(">\n");
+                } else {
+                    out.push_str(&format!("> {line}\n"));
+                }
+            }
+            out.push_str(&format!(
+                "\n_Note {}, {}_\n",
+                note.id,
+                note.created_at.format("%Y-%m-%d %H:%M")
+            ));
+        }
+    }
+    out
+}
+
+/// `<number>-<slug>.md`, keeping only lowercase ASCII letters and digits from the title so
+/// the name is safe on every filesystem; the number keeps names unique.
+fn issue_file_name(number: u64, title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= MAX_SLUG_LEN {
+            break;
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        format!("{number}.md")
+    } else {
+        format!("{number}-{slug}.md")
+    }
+}
+
+fn escape_link_text(text: &str) -> String {
+    text.replace('[', "\\[").replace(']', "\\]")
+}
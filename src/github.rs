@@ -1,6 +1,40 @@
-use anyhow::{Context, Result, anyhow};
-use octocrab::Octocrab;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use octocrab::etag::{EntityTag, Etagged};
 use octocrab::models::Repository;
+use octocrab::models::issues::Comment;
+use octocrab::models::timelines::TimelineEvent;
+use octocrab::{FromResponse, Octocrab, Page};
+use serde::Deserialize;
+
+use crate::config;
+
+/// REST endpoint used for every API call unless a GitHub Enterprise base URL is configured.
+pub const API_BASE_URL: &str = "https://api.github.com";
+
+const ISSUES_PER_PAGE: u8 = 50;
+
+/// Site that issue and file permalinks point at.
+pub const WEB_BASE_URL: &str = "https://github.com";
+
+/// API root to call: the configured Enterprise base URL, or github.com's.
+pub fn api_base_url(base_url: Option<&str>) -> &str {
+    base_url.unwrap_or(API_BASE_URL)
+}
+
+/// Web root matching an API root; an Enterprise Server at `https://ghe.example.com/api/v3`
+/// serves its pages from `https://ghe.example.com`.
+pub fn web_base_url(base_url: Option<&str>) -> String {
+    match base_url.map(|base| base.trim_end_matches('/')) {
+        Some(API_BASE_URL) | None => WEB_BASE_URL.to_string(),
+        Some(base) => base.strip_suffix("/api/v3").unwrap_or(base).to_string(),
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct RepoSpec {
@@ -10,7 +44,7 @@ pub struct RepoSpec {
 
 impl RepoSpec {
     pub fn parse(repo: &str) -> Result<Self> {
-        let mut parts = repo.splitn(2, '/');
+        let mut parts = config::strip_repo_url(repo).splitn(2, '/');
         let owner = parts
             .next()
             .map(str::trim)
@@ -28,30 +62,247 @@ impl RepoSpec {
     }
 }
 
+/// Canonical web URL of an issue.
+pub fn issue_url(base_url: Option<&str>, repo: &str, number: u64) -> String {
+    format!("{}/{repo}/issues/{number}", web_base_url(base_url))
+}
+
+/// Where a note points, as stored in `notes.anchor`: `file:<path>#L<line>`, `comment:<id>` or
+/// `quote:<snippet of the issue body>`. Text without one of these prefixes is a quote, which is
+/// how anchors were written before the prefixes existed.
+#[derive(Debug, PartialEq)]
+pub enum Anchor<'a> {
+    File {
+        path: &'a str,
+        line: Option<&'a str>,
+    },
+    Comment(u64),
+    Quote(&'a str),
+}
+
+impl<'a> Anchor<'a> {
+    pub fn parse(anchor: &'a str) -> Result<Self> {
+        if let Some(location) = anchor.strip_prefix("file:") {
+            let (path, line) = match location.split_once('#') {
+                Some((path, line)) => (path, Some(line).filter(|line| !line.is_empty())),
+                None => (location, None),
+            };
+            let path = path.trim_start_matches('/');
+            ensure!(!path.is_empty(), "anchor `{anchor}` does not name a file");
+            ensure!(
+                line.is_none_or(|line| line.starts_with('L')),
+                "anchor `{anchor}` should point at a line like `#L42`"
+            );
+            return Ok(Self::File { path, line });
+        }
+        if let Some(id) = anchor.strip_prefix("comment:") {
+            let id = id
+                .trim()
+                .parse()
+                .with_context(|| format!("anchor `{anchor}` has no numeric comment id"))?;
+            return Ok(Self::Comment(id));
+        }
+        Ok(Self::Quote(anchor.strip_prefix("quote:").unwrap_or(anchor)))
+    }
+}
+
+impl fmt::Display for Anchor<'_> {
+    /// The stored form, always with its prefix.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File {
+                path,
+                line: Some(line),
+            } => write!(f, "file:{path}#{line}"),
+            Self::File { path, line: None } => write!(f, "file:{path}"),
+            Self::Comment(id) => write!(f, "comment:{id}"),
+            Self::Quote(text) => write!(f, "quote:{text}"),
+        }
+    }
+}
+
+/// Builds the web URL for a note anchor: a file links to the line on the default branch, a
+/// comment to the comment on the issue, and a quote to the issue with the snippet highlighted.
+pub fn anchor_url(base_url: Option<&str>, repo: &str, number: i64, anchor: &str) -> Result<String> {
+    let issue = issue_url(base_url, repo, number as u64);
+    Ok(match Anchor::parse(anchor)? {
+        Anchor::File { path, line } => format!(
+            "{}/{repo}/blob/HEAD/{path}{}",
+            web_base_url(base_url),
+            line.map(|line| format!("#{line}")).unwrap_or_default()
+        ),
+        Anchor::Comment(id) => format!("{issue}#issuecomment-{id}"),
+        Anchor::Quote(text) => format!("{issue}#:~:text={}", encode_text_fragment(text)),
+    })
+}
+
+/// Percent-encodes everything but ASCII letters and digits; `-`, `,` and `&` are syntax
+/// inside a `#:~:text=` fragment, so they must not appear literally.
+fn encode_text_fragment(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() {
+                (byte as char).to_string()
+            } else {
+                format!("%{byte:02X}")
+            }
+        })
+        .collect()
+}
+
+/// Retries per request when no `--max-retries` is given.
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+/// Longest we wait for a rate limit to reset before giving up on a request.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(15 * 60);
+
+/// Result of a conditional issue listing; `issues` is `None` when GitHub answered
+/// 304 Not Modified for the stored ETag.
+pub struct IssueListing {
+    pub issues: Option<Vec<octocrab::models::issues::Issue>>,
+    /// ETag of the first page, to send as `If-None-Match` next time.
+    pub etag: Option<String>,
+}
+
 pub struct GithubClient {
     inner: Octocrab,
     repo: RepoSpec,
+    max_retries: u32,
+}
+
+/// Builds an authenticated `Octocrab`, pointed at `base_url` for GitHub Enterprise Server;
+/// clones share its connection pool.
+pub fn build_client(token: &str, base_url: Option<&str>) -> Result<Octocrab> {
+    let mut builder = Octocrab::builder().personal_token(token.to_string());
+    if let Some(base_url) = base_url {
+        builder = builder
+            .base_uri(base_url)
+            .with_context(|| format!("invalid GitHub base URL {base_url}"))?;
+    }
+    builder.build().context("failed to build GitHub client")
 }
 
 impl GithubClient {
-    pub async fn new(token: &str, repo: RepoSpec) -> Result<Self> {
-        let inner = Octocrab::builder()
-            .personal_token(token.to_string())
-            .build()
-            .context("failed to build GitHub client")?;
-        Ok(Self { inner, repo })
+    pub async fn new(token: &str, base_url: Option<&str>, repo: RepoSpec) -> Result<Self> {
+        Ok(Self::with_client(build_client(token, base_url)?, repo))
+    }
+
+    /// Wraps an existing client, e.g. one shared across the repositories of a sync.
+    pub fn with_client(inner: Octocrab, repo: RepoSpec) -> Self {
+        Self {
+            inner,
+            repo,
+            max_retries: DEFAULT_MAX_RETRIES,
+        }
     }
 
-    pub async fn list_issues_all(&self) -> Result<Vec<octocrab::models::issues::Issue>> {
+    /// How often page fetches are retried after rate limits or transient failures.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Fetches every issue page, or only issues updated at or after `since` when given. With
+    /// `concurrency > 1` and a known last page, pages after the first are requested that many at
+    /// a time; results keep their page order either way. When `etag` is given the first page is
+    /// requested conditionally, and a 304 ends the listing without fetching anything else.
+    /// `on_page` is called with the number of issues on each page as it arrives.
+    pub async fn list_issues_all(
+        &self,
+        concurrency: usize,
+        since: Option<DateTime<Utc>>,
+        etag: Option<&str>,
+        on_page: &(dyn Fn(usize) + Sync),
+    ) -> Result<IssueListing> {
+        let repo = format!("{}/{}", self.repo.owner, self.repo.name);
+        let etag = etag.and_then(|etag| etag.parse::<EntityTag>().ok());
+        let first = with_retries(&self.inner, self.max_retries, || {
+            self.first_issues_page(since, etag.clone())
+        })
+        .await
+        .with_context(|| format!("failed to fetch issues page 1 of {repo}"))?;
+        let etag = first.etag.map(|etag| etag.to_string());
+        let Some(page) = first.value else {
+            return Ok(IssueListing { issues: None, etag });
+        };
+        on_page(page.items.len());
+        let issues = self
+            .remaining_issue_pages(page, concurrency, since, on_page)
+            .await?;
+        Ok(IssueListing {
+            issues: Some(issues),
+            etag,
+        })
+    }
+
+    async fn remaining_issue_pages(
+        &self,
+        mut page: Page<octocrab::models::issues::Issue>,
+        concurrency: usize,
+        since: Option<DateTime<Utc>>,
+        on_page: &(dyn Fn(usize) + Sync),
+    ) -> Result<Vec<octocrab::models::issues::Issue>> {
+        let repo = format!("{}/{}", self.repo.owner, self.repo.name);
+
+        let mut items = page.items.clone();
+        if concurrency > 1
+            && let Some(last) = page.number_of_pages()
+        {
+            let mut pages = stream::iter(2..=last)
+                .map(|number| async move {
+                    with_retries(&self.inner, self.max_retries, || {
+                        self.issues_page(Some(number), since)
+                    })
+                    .await
+                    .map_err(|err| (number, err))
+                })
+                .buffered(concurrency);
+            while let Some(page) = pages.next().await {
+                let page = page.map_err(|(number, err)| {
+                    anyhow::Error::new(err)
+                        .context(format!("failed to fetch issues page {number} of {repo}"))
+                })?;
+                on_page(page.items.len());
+                items.extend(page.items);
+            }
+            return Ok(items);
+        }
+
+        let mut number = 1;
+        while page.next.is_some() {
+            number += 1;
+            let next = page.next.clone();
+            page = with_retries(&self.inner, self.max_retries, || {
+                self.inner
+                    .get_page::<octocrab::models::issues::Issue>(&next)
+            })
+            .await
+            .with_context(|| format!("failed to fetch issues page {number} of {repo}"))?
+            .ok_or_else(|| anyhow!("missing issues page {number} of {repo}"))?;
+            on_page(page.items.len());
+            items.extend(page.items.clone());
+        }
+
+        Ok(items)
+    }
+
+    /// Fetches closed issues updated at or after `since`, following every page.
+    pub async fn list_closed_since(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<octocrab::models::issues::Issue>> {
         let mut page = self
             .inner
             .issues(&self.repo.owner, &self.repo.name)
             .list()
-            .state(octocrab::params::State::All)
-            .per_page(50)
+            .state(octocrab::params::State::Closed)
+            .since(since)
+            .per_page(ISSUES_PER_PAGE)
             .send()
             .await
-            .context("failed to fetch issues")?;
+            .context("failed to fetch closed issues")?;
 
         let mut items = page.items.clone();
         while page.next.is_some() {
@@ -59,13 +310,110 @@ impl GithubClient {
                 .inner
                 .get_page::<octocrab::models::issues::Issue>(&page.next)
                 .await
-                .context("failed to fetch next issues page")?
+                .context("failed to fetch next closed issues page")?
                 .ok_or_else(|| anyhow!("missing issues page"))?;
             items.extend(page.items.clone());
         }
+        Ok(items)
+    }
+
+    /// The first issues page, sent with `If-None-Match` when `etag` is given. The issues
+    /// builder cannot set headers, so this goes through the raw request API.
+    async fn first_issues_page(
+        &self,
+        since: Option<DateTime<Utc>>,
+        etag: Option<EntityTag>,
+    ) -> octocrab::Result<Etagged<Page<octocrab::models::issues::Issue>>> {
+        let mut route = format!(
+            "/repos/{}/{}/issues?state=all&per_page={ISSUES_PER_PAGE}",
+            self.repo.owner, self.repo.name
+        );
+        if let Some(since) = since {
+            route.push_str(&format!("&since={}", since.format("%Y-%m-%dT%H:%M:%SZ")));
+        }
+        let mut headers = http::HeaderMap::new();
+        if let Some(etag) = etag {
+            EntityTag::insert_if_none_match_header(&mut headers, etag)?;
+        }
+        let response = self.inner._get_with_headers(route, Some(headers)).await?;
+        let etag = EntityTag::extract_from_response(&response);
+        if response.status() == http::StatusCode::NOT_MODIFIED {
+            return Ok(Etagged { etag, value: None });
+        }
+        let response = octocrab::map_github_error(response).await?;
+        let page = Page::from_response(response).await?;
+        Ok(Etagged {
+            etag,
+            value: Some(page),
+        })
+    }
+
+    async fn issues_page(
+        &self,
+        number: Option<u32>,
+        since: Option<DateTime<Utc>>,
+    ) -> octocrab::Result<Page<octocrab::models::issues::Issue>> {
+        let issues = self.inner.issues(&self.repo.owner, &self.repo.name);
+        let mut request = issues
+            .list()
+            .state(octocrab::params::State::All)
+            .per_page(ISSUES_PER_PAGE);
+        if let Some(number) = number {
+            request = request.page(number);
+        }
+        if let Some(since) = since {
+            request = request.since(since);
+        }
+        request.send().await
+    }
+
+    pub async fn list_comments(&self, number: u64) -> Result<Vec<Comment>> {
+        let mut page = self
+            .inner
+            .issues(&self.repo.owner, &self.repo.name)
+            .list_comments(number)
+            .per_page(100)
+            .send()
+            .await
+            .with_context(|| format!("failed to fetch comments for issue #{number}"))?;
+
+        let mut items = page.items.clone();
+        while page.next.is_some() {
+            page = self
+                .inner
+                .get_page::<Comment>(&page.next)
+                .await
+                .with_context(|| format!("failed to fetch next comments page for issue #{number}"))?
+                .ok_or_else(|| anyhow!("missing comments page"))?;
+            items.extend(page.items.clone());
+        }
+        Ok(items)
+    }
+
+    pub async fn list_timeline(&self, number: u64) -> Result<Vec<TimelineEvent>> {
+        let mut page = self
+            .inner
+            .issues(&self.repo.owner, &self.repo.name)
+            .list_timeline_events(number)
+            .per_page(100)
+            .send()
+            .await
+            .with_context(|| format!("failed to fetch timeline for issue #{number}"))?;
+
+        let mut items = page.items.clone();
+        while page.next.is_some() {
+            page = self
+                .inner
+                .get_page::<TimelineEvent>(&page.next)
+                .await
+                .with_context(|| format!("failed to fetch next timeline page for issue #{number}"))?
+                .ok_or_else(|| anyhow!("missing timeline page"))?;
+            items.extend(page.items.clone());
+        }
 
         Ok(items)
     }
+
     pub async fn get_issue(&self, number: u64) -> Result<octocrab::models::issues::Issue> {
         self.inner
             .issues(&self.repo.owner, &self.repo.name)
@@ -73,45 +421,284 @@ impl GithubClient {
             .await
             .with_context(|| format!("failed to fetch issue #{number}"))
     }
+
+    /// Returns `open`, `merged` or `closed` for pull request `number`.
+    pub async fn pull_status(&self, number: u64) -> Result<&'static str> {
+        let pull = self
+            .inner
+            .pulls(&self.repo.owner, &self.repo.name)
+            .get(number)
+            .await
+            .with_context(|| format!("failed to fetch pull request #{number}"))?;
+        Ok(if pull.merged_at.is_some() {
+            "merged"
+        } else if matches!(pull.state, Some(octocrab::models::IssueState::Closed)) {
+            "closed"
+        } else {
+            "open"
+        })
+    }
+
+    /// Fails unless the repository exists and the token can read it.
+    pub async fn check_access(&self) -> Result<()> {
+        self.inner
+            .repos(&self.repo.owner, &self.repo.name)
+            .get()
+            .await
+            .with_context(|| format!("failed to read {}/{}", self.repo.owner, self.repo.name))?;
+        Ok(())
+    }
+
+    pub async fn create_issue(
+        &self,
+        title: &str,
+        body: &str,
+    ) -> Result<octocrab::models::issues::Issue> {
+        self.inner
+            .issues(&self.repo.owner, &self.repo.name)
+            .create(title)
+            .body(body)
+            .send()
+            .await
+            .context("failed to create issue")
+    }
+}
+
+/// Renders an error chain on one line, dropping the backtraces octocrab embeds in its messages.
+pub fn describe_error(err: &anyhow::Error) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    for cause in err.chain() {
+        let text = cause.to_string();
+        let text = text.split("\n\nFound at").next().unwrap_or_default().trim();
+        if !text.is_empty() && !parts.iter().any(|part| part.contains(text)) {
+            parts.push(text.to_string());
+        }
+    }
+    parts.join(": ")
 }
 
-pub async fn list_authenticated_repos(token: &str) -> Result<Vec<String>> {
-    let octo = Octocrab::builder()
-        .personal_token(token.to_string())
-        .build()
-        .context("failed to build GitHub client")?;
+/// Runs `request`, retrying up to `max_retries` times after rate limits (waiting for the
+/// reset reported by GitHub) and transient failures (exponential backoff from one second).
+async fn with_retries<T, F, Fut>(
+    octo: &Octocrab,
+    max_retries: u32,
+    mut request: F,
+) -> octocrab::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = octocrab::Result<T>>,
+{
+    let mut attempt = 0;
+    loop {
+        let err = match request().await {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+        if attempt >= max_retries {
+            return Err(err);
+        }
+        let backoff = Duration::from_secs(1 << attempt.min(6));
+        let delay = match &err {
+            octocrab::Error::GitHub { source, .. }
+                if source.message.to_lowercase().contains("rate limit") =>
+            {
+                match rate_limit_reset(octo).await {
+                    Some(wait) if wait > MAX_RATE_LIMIT_WAIT => return Err(err),
+                    Some(wait) => wait.max(backoff),
+                    None => backoff,
+                }
+            }
+            octocrab::Error::GitHub { source, .. } if is_server_error(&source.message) => backoff,
+            octocrab::Error::Hyper { .. } | octocrab::Error::Service { .. } => backoff,
+            _ => return Err(err),
+        };
+        attempt += 1;
+        eprintln!(
+            "  GitHub request failed; retrying in {}s (attempt {attempt} of {max_retries})",
+            delay.as_secs()
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
 
-    let mut page = octo
-        .current()
-        .list_repos_for_authenticated_user()
-        .per_page(100)
-        .send()
+/// Time until the core rate limit resets, when it is currently exhausted.
+async fn rate_limit_reset(octo: &Octocrab) -> Option<Duration> {
+    let limit = octo.ratelimit().get().await.ok()?;
+    if limit.resources.core.remaining > 0 {
+        return None;
+    }
+    let reset = DateTime::<Utc>::from_timestamp(limit.resources.core.reset as i64, 0)?;
+    (reset - Utc::now() + chrono::Duration::seconds(1))
+        .to_std()
+        .ok()
+}
+
+fn is_server_error(message: &str) -> bool {
+    let message = message.to_lowercase();
+    [
+        "server error",
+        "bad gateway",
+        "service unavailable",
+        "timeout",
+    ]
+    .iter()
+    .any(|needle| message.contains(needle))
+}
+
+/// True when the error chain contains GitHub's 404 response.
+pub fn is_not_found(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<octocrab::Error>(),
+            Some(octocrab::Error::GitHub { source, .. }) if source.message == "Not Found"
+        )
+    })
+}
+
+/// The account a token authenticates as.
+#[derive(Debug, Deserialize)]
+pub struct Identity {
+    pub login: String,
+    pub name: Option<String>,
+}
+
+pub async fn current_user(token: &str, base_url: Option<&str>) -> Result<Identity> {
+    let octo = build_client(token, base_url)?;
+    octo.get("/user", None::<&()>)
         .await
-        .context("failed to fetch repositories")?;
+        .context("failed to fetch the authenticated user")
+}
 
-    let mut names = Vec::new();
+/// Checks `token` against the API and returns the login it authenticates as.
+pub async fn verify_token(token: &str, base_url: Option<&str>) -> Result<String> {
+    match current_user(token, base_url).await {
+        Ok(identity) => Ok(identity.login),
+        Err(err) if is_bad_credentials(&err) => {
+            bail!("the GitHub token appears invalid (GitHub answered 401 Bad credentials)")
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// True when the error chain contains GitHub's 401 response.
+fn is_bad_credentials(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<octocrab::Error>(),
+            Some(octocrab::Error::GitHub { source, .. }) if source.message == "Bad credentials"
+        )
+    })
+}
+
+/// Current rate-limit buckets for `token`.
+pub async fn rate_limit(
+    token: &str,
+    base_url: Option<&str>,
+) -> Result<octocrab::models::RateLimit> {
+    let octo = build_client(token, base_url)?;
+    octo.ratelimit()
+        .get()
+        .await
+        .context("failed to fetch the rate limit status")
+}
+
+/// A repository visible to the authenticated user.
+#[derive(Debug)]
+pub struct RemoteRepo {
+    pub full_name: String,
+    pub topics: Vec<String>,
+    pub pushed_at: Option<DateTime<Utc>>,
+}
+
+pub async fn list_authenticated_repos(
+    token: &str,
+    base_url: Option<&str>,
+) -> Result<Vec<RemoteRepo>> {
+    let octo = build_client(token, base_url)?;
+
+    let mut page = with_retries(&octo, DEFAULT_MAX_RETRIES, || {
+        octo.current()
+            .list_repos_for_authenticated_user()
+            .per_page(100)
+            .send()
+    })
+    .await
+    .context("failed to fetch repositories")?;
+
+    let mut repos = Vec::new();
 
     loop {
         for repo in &page.items {
-            if let Some(full) = &repo.full_name {
-                names.push(full.clone());
+            let full_name = if let Some(full) = &repo.full_name {
+                full.clone()
             } else if let Some(owner) = repo.owner.as_ref() {
-                names.push(format!("{}/{}", owner.login, repo.name));
+                format!("{}/{}", owner.login, repo.name)
             } else {
-                names.push(repo.name.clone());
-            }
+                repo.name.clone()
+            };
+            repos.push(RemoteRepo {
+                full_name,
+                topics: repo.topics.clone().unwrap_or_default(),
+                pushed_at: repo.pushed_at,
+            });
         }
 
         if page.next.is_some() {
-            page = octo
-                .get_page::<Repository>(&page.next)
-                .await
-                .context("failed to fetch next repositories page")?
-                .ok_or_else(|| anyhow!("missing repositories page"))?;
+            let next = page.next.clone();
+            page = with_retries(&octo, DEFAULT_MAX_RETRIES, || {
+                octo.get_page::<Repository>(&next)
+            })
+            .await
+            .context("failed to fetch next repositories page")?
+            .ok_or_else(|| anyhow!("missing repositories page"))?;
         } else {
             break;
         }
     }
 
-    Ok(names)
+    Ok(repos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anchors_parse_by_prefix_and_default_to_quotes() {
+        assert_eq!(
+            Anchor::parse("file:/src/main.rs#L42").unwrap(),
+            Anchor::File {
+                path: "src/main.rs",
+                line: Some("L42")
+            }
+        );
+        assert_eq!(Anchor::parse("comment:123").unwrap(), Anchor::Comment(123));
+        assert_eq!(
+            Anchor::parse("quote:steps to reproduce").unwrap(),
+            Anchor::Quote("steps to reproduce")
+        );
+        assert_eq!(
+            Anchor::parse("steps to reproduce").unwrap().to_string(),
+            "quote:steps to reproduce"
+        );
+        assert!(Anchor::parse("file:#L1").is_err());
+        assert!(Anchor::parse("comment:abc").is_err());
+    }
+
+    #[test]
+    fn every_anchor_kind_has_a_url() {
+        let url = |anchor| anchor_url(None, "o/n", 7, anchor).unwrap();
+        assert_eq!(
+            url("file:src/lib.rs#L3"),
+            "https://github.com/o/n/blob/HEAD/src/lib.rs#L3"
+        );
+        assert_eq!(
+            url("comment:99"),
+            "https://github.com/o/n/issues/7#issuecomment-99"
+        );
+        assert_eq!(
+            url("quote:a-b c"),
+            "https://github.com/o/n/issues/7#:~:text=a%2Db%20c"
+        );
+    }
 }
@@ -1,33 +1,69 @@
+mod completions;
 mod config;
+mod export;
 mod github;
 mod storage;
 
 use std::collections::HashSet;
-use std::path::PathBuf;
+use std::fs;
+use std::io::{self, IsTerminal as _, Write as _};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
 
-use anyhow::{Context as _, Result, bail, ensure};
-use clap::{Args, Parser, Subcommand};
+use anyhow::{Context as _, Result, anyhow, bail, ensure};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveTime, Utc};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
 use config::Config;
+use futures::stream::{self, StreamExt};
 use github::{GithubClient, RepoSpec};
-use storage::{Storage, StoredIssueDetail};
+use octocrab::models::issues::{Comment, Issue};
+use regex::Regex;
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+use storage::{
+    Archive, ExportedNote, IssueFilter, IssueOrder, RepoStats, Storage, StoredIssueDetail,
+    StoredIssueSummary,
+};
+use tinytemplate::TinyTemplate;
+
+/// `sync_state` resource holding the newest issue `updated_at` seen by a successful sync.
+const ISSUES_CURSOR: &str = "issues";
+/// `sync_state` resource holding the ETag of the last incremental issue listing.
+const ISSUES_ETAG: &str = "issues_etag";
 
 struct AppContext {
     config: Config,
     config_path: PathBuf,
+    database_path: PathBuf,
     storage: Storage,
+    /// Take `GITHUB_TOKEN` over the configured token (`--prefer-env`).
+    prefer_env: bool,
+    /// Never talk to GitHub (`--offline`); commands that would have to fail instead.
+    offline: bool,
 }
 
 impl AppContext {
-    fn load() -> Result<Self> {
-        let (config, path) = Config::load()?;
-        let storage = Storage::open()?;
+    fn load(config_path: Option<&Path>, data_dir: Option<&Path>) -> Result<Self> {
+        let (config, path) = Config::load(config_path)?;
+        let storage = Storage::open(data_dir)?;
         Ok(Self {
             config,
             config_path: path,
+            database_path: storage::database_path(data_dir)?,
             storage,
+            prefer_env: false,
+            offline: false,
         })
     }
 
+    /// Fails with a clear message when offline mode is on; call before talking to GitHub.
+    fn ensure_online(&self, what: &str) -> Result<()> {
+        ensure!(!self.offline, "{what} needs GitHub and offline mode is on");
+        Ok(())
+    }
+
     fn save(&self) -> Result<()> {
         self.config.save(&self.config_path)
     }
@@ -41,6 +77,26 @@ impl AppContext {
     propagate_version = true
 )]
 struct Cli {
+    /// Output format for commands that support machine-readable output
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+    /// Use the GITHUB_TOKEN environment variable even when a token is configured
+    #[arg(long, global = true)]
+    prefer_env: bool,
+    /// Read and write the config file at this path instead of the default location
+    #[arg(long = "config", global = true, value_name = "path")]
+    config_file: Option<PathBuf>,
+    /// Keep the cache database in this directory instead of the default data directory
+    #[arg(long, global = true, value_name = "path", env = "NOTEHUB_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+    /// Never contact GitHub; commands that need it fail instead (also `NOTEHUB_OFFLINE=1`)
+    #[arg(
+        long,
+        global = true,
+        env = "NOTEHUB_OFFLINE",
+        value_parser = clap::builder::FalseyValueParser::new()
+    )]
+    offline: bool,
     #[command(subcommand)]
     command: Command,
 }
@@ -66,6 +122,59 @@ enum Command {
         #[command(subcommand)]
         action: NoteAction,
     },
+    /// Show which GitHub account the configured token belongs to
+    Whoami,
+    /// Show how much of the GitHub API rate limit is left
+    Ratelimit,
+    /// Check the config, token, cache database and repositories, printing a pass/fail list
+    Doctor {
+        /// Also check the token and every configured repository against GitHub
+        #[arg(long)]
+        online: bool,
+    },
+    /// Manage the configuration file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Populate the labels table from the label strings of issues cached by older versions
+    MigrateLabels,
+    /// Summarize what is in the local cache
+    Stats {
+        /// Break the numbers down per repository
+        #[arg(long)]
+        per_repo: bool,
+    },
+    /// Inspect or back up the local cache database
+    Db {
+        #[command(subcommand)]
+        action: DbAction,
+    },
+    /// Restore issues and notes from a `notehub export --format json` file
+    Import {
+        /// JSON file written by `notehub export --format json`
+        file: PathBuf,
+    },
+    /// Write a repository's cached issues and notes to a directory of Markdown files, or with
+    /// `--format json` the whole cache to a single JSON document
+    Export {
+        /// Repository to export (defaults to the active repo; to every cached one for JSON)
+        #[arg(long, value_name = "owner/name")]
+        repo: Option<String>,
+        /// Directory for the Markdown files, or file for the JSON document (stdout if omitted)
+        #[arg(long, value_name = "path")]
+        out: Option<PathBuf>,
+    },
+    /// Fill in or refresh parts of the local cache on demand
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Print a shell completion script (e.g. `notehub completions bash > ~/.bash_completion.d/notehub`)
+    Completions {
+        #[arg(value_enum)]
+        shell: completions::Shell,
+    },
 }
 
 #[derive(Args)]
@@ -73,6 +182,75 @@ struct SyncArgs {
     /// Sync only the specified repository (owner/name). May be supplied multiple times.
     #[arg(long, value_name = "owner/name")]
     repo: Vec<String>,
+    /// Also cache timeline events (one extra request per changed issue)
+    #[arg(long)]
+    timeline: bool,
+    /// Also cache comments (one extra request per changed issue that has comments)
+    #[arg(long)]
+    with_comments: bool,
+    /// Truncate cached bodies larger than this many bytes (overrides `max_body_size` in config)
+    #[arg(long, value_name = "bytes")]
+    max_body_size: Option<usize>,
+    /// Write a JSON summary of each repository's outcome to this file
+    #[arg(long, value_name = "path")]
+    report: Option<PathBuf>,
+    /// Continue the last interrupted or partially failed sync, skipping repositories it finished
+    #[arg(long, conflicts_with = "repo")]
+    resume: bool,
+    /// Fetch issue pages concurrently, n at a time (default 4) once the page count is known
+    #[arg(
+        long,
+        value_name = "n",
+        num_args = 0..=1,
+        default_missing_value = "4",
+        value_parser = clap::value_parser!(u8).range(1..=16)
+    )]
+    parallel_pages: Option<u8>,
+    /// Fetch this many repositories concurrently; writes to the cache still happen one
+    /// repository at a time, in order
+    #[arg(
+        long,
+        value_name = "n",
+        default_value_t = 4,
+        value_parser = clap::value_parser!(u8).range(1..=16)
+    )]
+    jobs: u8,
+    /// Retry each page fetch up to this many times after rate limits or transient errors
+    #[arg(long, value_name = "n", default_value_t = github::DEFAULT_MAX_RETRIES)]
+    max_retries: u32,
+    /// Stop at the first repository that fails instead of syncing the rest
+    #[arg(long)]
+    fail_fast: bool,
+    /// Check afterwards that every fetched issue was fully written to the cache
+    #[arg(long)]
+    verify_after: bool,
+    /// Also fetch issues closed (or otherwise updated) since this date and merge them into the
+    /// batch (YYYY-MM-DD, RFC 3339, or `7d`/`2w` ago); does not move any sync cursor
+    #[arg(long, value_name = "date", value_parser = parse_since)]
+    include_closed_since: Option<DateTime<Utc>>,
+    /// Only fetch issues updated since this date (YYYY-MM-DD, RFC 3339, or `7d`/`2w` ago),
+    /// instead of since the last successful sync
+    #[arg(
+        long,
+        value_name = "date",
+        value_parser = parse_since,
+        conflicts_with_all = ["full", "prune"]
+    )]
+    since: Option<DateTime<Utc>>,
+    /// Refetch every issue instead of only those updated since the last successful sync
+    #[arg(long)]
+    full: bool,
+    /// Remove cached issues GitHub no longer returns (deleted or transferred); issues with
+    /// notes are kept
+    #[arg(long)]
+    prune: bool,
+    /// Let `--prune` also remove issues that have notes, deleting those notes
+    #[arg(long, requires = "prune")]
+    force_prune_with_notes: bool,
+    /// Fetch from GitHub but only report how many issues would be created, updated or left
+    /// unchanged; nothing is written to the cache
+    #[arg(long, conflicts_with_all = ["prune", "verify_after"])]
+    dry_run: bool,
 }
 
 #[derive(Args)]
@@ -80,9 +258,21 @@ struct InitArgs {
     /// GitHub personal access token used for API calls
     #[arg(long)]
     token: Option<String>,
+    /// Keep the token in the OS keyring; the config only records that it lives there
+    #[arg(long, requires = "token")]
+    use_keyring: bool,
+    /// Remove the stored token from the config and the OS keyring
+    #[arg(long, conflicts_with = "token")]
+    clear_token: bool,
     /// One or more repositories to add (owner/name). May be repeated.
     #[arg(long, value_name = "owner/name")]
     repo: Vec<String>,
+    /// API URL of a GitHub Enterprise Server (e.g. https://ghe.example.com/api/v3)
+    #[arg(long, value_name = "url")]
+    base_url: Option<String>,
+    /// Store the token without checking it against the GitHub API (e.g. while offline)
+    #[arg(long, requires = "token")]
+    no_verify: bool,
 }
 
 #[derive(Subcommand)]
@@ -95,22 +285,422 @@ enum IssueAction {
         /// List cached issues for all configured repositories
         #[arg(long, default_value_t = false)]
         all: bool,
+        /// Only show issues with at least this many assignees
+        #[arg(long, value_name = "n")]
+        min_assignees: Option<u32>,
+        /// Only show issues with at most this many assignees (0 finds unowned issues)
+        #[arg(long, value_name = "n")]
+        max_assignees: Option<u32>,
+        /// Show additional details such as the assignee count
+        #[arg(long, short)]
+        verbose: bool,
+        /// Group the listing; `state-reason` splits closed issues into completed vs. not planned
+        #[arg(long, value_enum, value_name = "key")]
+        group_by: Option<GroupBy>,
+        /// Print one JSON object per issue per line (NDJSON) instead of text
+        #[arg(long, conflicts_with_all = ["group_by", "verbose"])]
+        json_lines: bool,
+        /// Only show issues in this state [default: open, or the repository's `list_state`]
+        #[arg(long, value_enum, conflicts_with = "group_by")]
+        state: Option<IssueState>,
+        /// Render each issue through this TinyTemplate file (e.g. `{number}: {title}`)
+        #[arg(
+            long,
+            visible_alias = "template-file",
+            value_name = "path",
+            conflicts_with_all = ["group_by", "verbose", "json_lines"]
+        )]
+        output_template_file: Option<PathBuf>,
+        /// Only show issues updated since this date (YYYY-MM-DD, RFC 3339, or `7d`/`2w` ago)
+        #[arg(long, value_name = "date", value_parser = parse_since, group = "updated_window")]
+        since: Option<DateTime<Utc>>,
+        /// Only show issues updated since midnight UTC today
+        #[arg(long, group = "updated_window")]
+        updated_today: bool,
+        /// Only show issues updated in the last 7 days
+        #[arg(long, group = "updated_window")]
+        updated_this_week: bool,
+        /// Only show issues with no description (missing or whitespace-only body)
+        #[arg(long, conflicts_with = "body_nonempty")]
+        body_empty: bool,
+        /// Only show issues that have a description
+        #[arg(long)]
+        body_nonempty: bool,
+        /// Only show issues opened by this login
+        #[arg(long, value_name = "login")]
+        author: Option<String>,
+        /// Only show issues this login opened, is assigned to, or is @-mentioned in
+        #[arg(long, value_name = "login")]
+        involves: Option<String>,
+        /// Only show issues assigned to this login; `@me` means the authenticated user
+        #[arg(long, value_name = "login")]
+        assignee: Option<String>,
+        /// Only show issues you opened (shorthand for `--author <your login>`)
+        #[arg(long, conflicts_with = "author")]
+        created_by_me: bool,
+        /// Omit the "(no cached issues)" / "No repositories configured" placeholders
+        #[arg(long)]
+        no_cache_warn: bool,
+        /// Only show issues whose body references this issue number (`#42`)
+        #[arg(long, value_name = "number")]
+        has_reference_to: Option<u64>,
+        /// Only show issues carrying this label. May be repeated; all must match.
+        #[arg(long, value_name = "name")]
+        label: Vec<String>,
+        /// Only show issues without any label
+        #[arg(long, conflicts_with = "label")]
+        no_label: bool,
+        /// Only show issues in this milestone (by title); `none` finds issues without one
+        #[arg(long, value_name = "title")]
+        milestone: Option<String>,
+        /// Which documents to list: issues, pull requests, or both
+        #[arg(long, value_enum, default_value_t = IssueKind::Issue)]
+        kind: IssueKind,
+        /// Order issues by this key (numbers and dates newest first, titles A to Z)
+        #[arg(long, value_enum, value_name = "key", default_value_t = IssueSort::Number)]
+        sort: IssueSort,
+        /// Reverse the sort order
+        #[arg(long)]
+        reverse: bool,
+        /// Show at most this many issues per repository
+        #[arg(long, value_name = "n", value_parser = clap::value_parser!(u32).range(1..))]
+        limit: Option<u32>,
+        /// Skip this many matching issues per repository first
+        #[arg(long, value_name = "n", default_value_t = 0)]
+        offset: u32,
+    },
+    /// Open a cached issue on GitHub in the browser
+    Open {
+        /// Issue number to open
+        number: u64,
+        /// Repository the issue belongs to (defaults to the active repo)
+        #[arg(long, value_name = "owner/name")]
+        repo: Option<String>,
+        /// Print the URL instead of launching a browser
+        #[arg(long)]
+        print_url: bool,
     },
     /// View a single issue by number
     View {
-        /// Issue number to display
-        number: u64,
+        /// Issue numbers to display, separated by a rule
+        #[arg(required = true, value_name = "number")]
+        numbers: Vec<u64>,
         /// Repository to read from (defaults to the active repo)
         #[arg(long, value_name = "owner/name")]
         repo: Option<String>,
+        /// Show cached timeline events (requires `sync --timeline`)
+        #[arg(long)]
+        timeline: bool,
+        /// Fetch the issue live from GitHub, e.g. to read a body truncated in the cache
+        #[arg(long)]
+        fetch: bool,
+        /// Show the title and body as cached at this time (YYYY-MM-DD, RFC 3339, or `7d`/`2w` ago)
+        #[arg(long, value_name = "timestamp", value_parser = parse_since, conflicts_with = "fetch")]
+        at: Option<DateTime<Utc>>,
+        /// Wrap the body at this column (defaults to the terminal width)
+        #[arg(long, value_name = "n", conflicts_with = "no_wrap")]
+        width: Option<usize>,
+        /// Print body lines exactly as stored
+        #[arg(long)]
+        no_wrap: bool,
+        /// Open the file line, comment or body snippet this note is anchored to in the browser
+        #[arg(long, value_name = "note_id", conflicts_with_all = ["fetch", "timeline"])]
+        open_anchor: Option<i64>,
+        /// For pull requests, fetch and show whether they are open, merged or closed
+        #[arg(long)]
+        include_pr_status: bool,
+        /// Also copy part of the issue to the clipboard
+        #[arg(
+            long,
+            value_enum,
+            value_name = "part",
+            num_args = 0..=1,
+            default_missing_value = "all"
+        )]
+        copy: Option<CopyPart>,
+        /// Follow the body with cached comments and local notes in timestamp order
+        #[arg(long)]
+        thread: bool,
+        /// Print only the stored body, exactly as stored, for piping into other tools
+        #[arg(
+            long,
+            conflicts_with_all = ["timeline", "width", "open_anchor", "include_pr_status", "copy", "thread"]
+        )]
+        raw: bool,
+    },
+    /// Full-text search over cached issue titles and bodies
+    Search {
+        /// Search terms (FTS5 query syntax)
+        query: String,
+        /// Repository to search (owner/name). May be repeated.
+        #[arg(long, value_name = "owner/name")]
+        repo: Vec<String>,
+        /// Search all configured repositories
+        #[arg(long, default_value_t = false)]
+        all: bool,
+        /// Number of tokens of context shown around each match (1-64)
+        #[arg(
+            long,
+            value_name = "tokens",
+            default_value_t = 64,
+            value_parser = clap::value_parser!(u8).range(1..=64)
+        )]
+        highlight_context: u8,
+        /// Maximum number of results to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Bucket open issues by how long ago they were opened
+    AgeReport {
+        /// Repository to report on (owner/name). May be repeated.
+        #[arg(long, value_name = "owner/name")]
+        repo: Vec<String>,
+        /// Report on all configured repositories
+        #[arg(long, default_value_t = false)]
+        all: bool,
+    },
+    /// Summarize what is cached: open/closed issues, notes, and sync times per repository
+    Stats {
+        /// Repository to summarize (owner/name). May be repeated.
+        #[arg(long, value_name = "owner/name")]
+        repo: Vec<String>,
+        /// Summarize all configured repositories, with a total row
+        #[arg(long, default_value_t = false)]
+        all: bool,
+    },
+    /// Set a local triage state (e.g. `blocked`, `reviewing`) on a cached issue
+    Triage {
+        /// Issue number to triage
+        number: u64,
+        /// New triage state
+        state: String,
+        /// Record why, as a note on the issue
+        #[arg(long, value_name = "reason")]
+        note: Option<String>,
+        /// Repository the issue belongs to (defaults to the active repo)
+        #[arg(long, value_name = "owner/name")]
+        repo: Option<String>,
+    },
+    /// Apply triage states from a file of `<issue> <state>` lines (`123 blocked`,
+    /// `owner/name#45 reviewing`)
+    BulkTriage {
+        /// File to read
+        file: PathBuf,
+        /// Repository for bare issue numbers (defaults to the configured repo caching them)
+        #[arg(long, value_name = "owner/name")]
+        repo: Option<String>,
+    },
+    /// Mark a cached issue as freshly synced without refetching it
+    Touch {
+        /// Issue number to touch
+        number: u64,
+        /// Repository the issue belongs to (defaults to the active repo)
+        #[arg(long, value_name = "owner/name")]
+        repo: Option<String>,
     },
+    /// Cache a Markdown file (`# Title` then body) as a local draft issue
+    ImportLocal {
+        /// Markdown file to import
+        file: PathBuf,
+        /// Repository the draft is meant for (defaults to the active repo)
+        #[arg(long, value_name = "owner/name")]
+        repo: Option<String>,
+    },
+    /// Create a local draft on GitHub and re-key it to the new issue number
+    PushDraft {
+        /// Draft number printed by `import-local` (e.g. -1)
+        #[arg(allow_hyphen_values = true)]
+        id: i64,
+        /// Repository the draft belongs to (defaults to the active repo)
+        #[arg(long, value_name = "owner/name")]
+        repo: Option<String>,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Serialize)]
+struct IssueListEntry<'a> {
+    repo: &'a str,
+    number: i64,
+    title: String,
+    state: Option<String>,
+    comment_count: i64,
+}
+
+#[derive(Serialize)]
+struct IssueViewJson<'a> {
+    number: i64,
+    title: &'a str,
+    body: Option<&'a str>,
+    updated_at: String,
+    labels: &'a [String],
+    state: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct IssueLine<'a> {
+    repo: &'a str,
+    #[serde(flatten)]
+    issue: &'a StoredIssueSummary,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RepoSort {
+    /// Most recently pushed first
+    Pushed,
+    /// Alphabetical by owner/name
+    Name,
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Rewrite an old-format config in the current layout (keeps a .bak copy)
+    Migrate,
+    /// Print where the config file and the cache database live
+    Path,
+    /// Print the current configuration with the token redacted
+    Show,
+}
+
+#[derive(Subcommand)]
+enum DbAction {
+    /// Open an interactive SQL prompt on the cache (uses `sqlite3` when installed)
+    Shell {
+        /// Use the built-in prompt even if `sqlite3` is available
+        #[arg(long)]
+        builtin: bool,
+    },
+    /// Dump the cache as a plain SQL script (CREATE TABLE + INSERT statements)
+    ExportSql {
+        /// File to write the script to
+        #[arg(long, value_name = "path")]
+        out: PathBuf,
+        /// Only export rows belonging to this repository (owner/name)
+        #[arg(long, value_name = "owner/name")]
+        repo: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Fetch full bodies for cached issues whose body is blank or truncated
+    Warm {
+        /// Repository to warm (owner/name). May be repeated.
+        #[arg(long, value_name = "owner/name")]
+        repo: Vec<String>,
+        /// Warm all configured repositories
+        #[arg(long, default_value_t = false)]
+        all: bool,
+        /// Only warm issues in this state
+        #[arg(long, value_enum)]
+        state: Option<IssueState>,
+        /// Only warm issues carrying this label. May be repeated.
+        #[arg(long, value_name = "name")]
+        label: Vec<String>,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum IssueState {
+    Open,
+    Closed,
+    All,
+}
+
+impl IssueState {
+    /// Parses a `list_state` override; the config loader already rejected unknown values.
+    fn from_config(value: &str) -> Result<Self> {
+        <Self as ValueEnum>::from_str(value, true)
+            .map_err(|_| anyhow!("unknown issue state `{value}`"))
+    }
+
+    /// Value to match `issue_meta.state` against, or `None` for every state.
+    fn filter(self) -> Option<&'static str> {
+        match self {
+            IssueState::Open => Some("open"),
+            IssueState::Closed => Some("closed"),
+            IssueState::All => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CopyPart {
+    Body,
+    Link,
+    Title,
+    /// Title, link and body together
+    All,
+}
+
+impl CopyPart {
+    fn extract(self, base_url: Option<&str>, repo: &str, issue: &StoredIssueDetail) -> String {
+        let link = github::issue_url(base_url, repo, issue.number as u64);
+        let body = issue.body.clone().unwrap_or_default();
+        match self {
+            CopyPart::Body => body,
+            CopyPart::Link => link,
+            CopyPart::Title => issue.title.clone(),
+            CopyPart::All => format!("#{} {}\n{link}\n\n{body}", issue.number, issue.title),
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum IssueKind {
+    Issue,
+    Pr,
+    All,
+}
+
+impl IssueKind {
+    fn filter(self) -> Option<&'static str> {
+        match self {
+            IssueKind::Issue => Some("issue"),
+            IssueKind::Pr => Some("pull_request"),
+            IssueKind::All => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum IssueSort {
+    Number,
+    Updated,
+    Created,
+    Title,
+}
+
+impl IssueSort {
+    fn order(self) -> IssueOrder {
+        match self {
+            IssueSort::Number => IssueOrder::Number,
+            IssueSort::Updated => IssueOrder::Updated,
+            IssueSort::Created => IssueOrder::Created,
+            IssueSort::Title => IssueOrder::Title,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum GroupBy {
+    StateReason,
 }
 
 #[derive(Subcommand)]
 enum RepoAction {
     /// Add a repository to the configuration
     Add {
-        repo: String,
+        #[arg(required_unless_present = "from_url", conflicts_with = "from_url")]
+        repo: Option<String>,
+        /// Take the repository from a GitHub repo, issue, pull request, or discussion URL
+        #[arg(long, value_name = "url")]
+        from_url: Option<String>,
         /// Also make the added repository the active one
         #[arg(long)]
         set_active: bool,
@@ -120,13 +710,43 @@ enum RepoAction {
         /// Repositories to skip while importing (owner/name).
         #[arg(long, value_name = "owner/name")]
         exclude: Vec<String>,
+        /// Only import repositories tagged with this topic. May be repeated (matches any).
+        #[arg(long, value_name = "topic")]
+        topic: Vec<String>,
+        /// Stop after adding this many new repositories
+        #[arg(long, value_name = "n")]
+        limit: Option<usize>,
+        /// Order in which repositories are considered (pair with --limit)
+        #[arg(long, value_enum)]
+        sort: Option<RepoSort>,
     },
     /// Remove a repository from the configuration
     Remove { repo: String },
+    /// Delete a repository's cached issues but keep it configured; the next sync starts afresh
+    Clear {
+        /// Repository to clear (owner/name)
+        repo: String,
+        /// Also delete notes attached to the cached issues
+        #[arg(long)]
+        notes: bool,
+    },
+    /// Rename a configured repository (e.g. after a transfer), keeping its cached issues and notes
+    Rename {
+        /// Current name (owner/name)
+        old: String,
+        /// New name (owner/name)
+        new: String,
+    },
     /// Set the active repository
     Use { repo: String },
     /// Show configured repositories
     List,
+    /// Flag configured repositories whose cache is stale, empty, or was never synced
+    Health {
+        /// Treat repositories not synced for this many days as stale
+        #[arg(long, value_name = "N", default_value_t = 7)]
+        stale_days: i64,
+    },
 }
 
 #[derive(Subcommand)]
@@ -137,40 +757,203 @@ enum NoteAction {
         number: u64,
         /// Text for the note
         text: String,
+        /// Repository of the issue (defaults to the active repository)
+        #[arg(long, value_name = "owner/name")]
+        repo: Option<String>,
+        /// Other issue numbers in the same repository this note relates to. May be repeated.
+        #[arg(long, value_name = "number")]
+        refs: Vec<u64>,
+        /// Tie the note to a file line (`file:path#L42`), a comment (`comment:<id>`) or a
+        /// snippet of the issue body (`quote:text`, or just the text)
+        #[arg(long, value_name = "anchor")]
+        anchor: Option<String>,
     },
     /// List notes for an issue
     List {
         /// Target issue number
         number: u64,
+        /// Repository of the issue (defaults to the active repository)
+        #[arg(long, value_name = "owner/name")]
+        repo: Option<String>,
+    },
+    /// Replace the text of a note
+    Edit {
+        /// Note id
+        id: i64,
+        /// New text for the note
+        text: String,
+    },
+    /// Delete a note
+    Remove {
+        /// Note id
+        id: i64,
+    },
+    /// Show how a note changed over time, one diff per edit
+    History {
+        /// Note id
+        id: i64,
+    },
+    /// Move a note one place earlier among its issue's notes
+    MoveUp {
+        /// Note id
+        id: i64,
+    },
+    /// Move a note one place later among its issue's notes
+    MoveDown {
+        /// Note id
+        id: i64,
+    },
+    /// Reattach a note to another issue, e.g. one filed against the wrong issue
+    Move {
+        /// Note id
+        id: i64,
+        /// Repository of the destination issue (owner/name)
+        repo: String,
+        /// Destination issue number
+        number: u64,
+    },
+    /// Cross-reference another issue from an existing note
+    LinkIssue {
+        /// Note id
+        id: i64,
+        /// Issue number the note relates to
+        number: u64,
+        /// Repository of the referenced issue (defaults to the note's repository)
+        #[arg(long, value_name = "owner/name")]
+        repo: Option<String>,
+    },
+    /// Write notes as a Markdown digest, grouped by issue
+    Export {
+        /// Only include notes created or updated since this date (YYYY-MM-DD, RFC 3339, or `7d`/`2w` ago)
+        #[arg(long, value_name = "date", value_parser = parse_since)]
+        since: Option<DateTime<Utc>>,
+        /// Only export notes attached to this repository (owner/name)
+        #[arg(long, value_name = "owner/name")]
+        repo: Option<String>,
+        /// Write to this file instead of stdout
+        #[arg(long, value_name = "path")]
+        out: Option<PathBuf>,
+    },
+    /// Summarize how much has been written: counts, words and recent activity
+    Stats {
+        /// Only count notes attached to this repository (owner/name)
+        #[arg(long, value_name = "owner/name")]
+        repo: Option<String>,
+    },
+    /// Find notes whose body matches a regular expression
+    Grep {
+        /// Regular expression (Rust `regex` syntax, e.g. `(?i)todo|fixme`)
+        pattern: String,
+        /// Only search notes attached to this repository (owner/name)
+        #[arg(long, value_name = "owner/name")]
+        repo: Option<String>,
+    },
+    /// Full-text search across all notes, best matches first
+    Search {
+        /// Search terms (FTS5 syntax, e.g. `flaky AND test`)
+        query: String,
+        /// Only search notes attached to this repository (owner/name)
+        #[arg(long, value_name = "owner/name")]
+        repo: Option<String>,
     },
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
-    let mut ctx = AppContext::load().context("failed to initialize application state")?;
+    // Completions must work before any config or cache exists.
+    if let Command::Completions { shell } = cli.command {
+        return completions::generate(shell, &mut Cli::command(), &mut io::stdout().lock());
+    }
+    // The doctor reports a broken config or cache instead of failing on it.
+    if let Command::Doctor { online } = cli.command {
+        ensure!(
+            !(online && cli.offline),
+            "--online cannot be combined with offline mode"
+        );
+        return run_doctor(&cli, online).await;
+    }
+    // Config commands only need the config file, so they keep working when the cache does not.
+    if let Command::Config { action } = cli.command {
+        let (config, config_path) = Config::load(cli.config_file.as_deref())?;
+        if !matches!(action, ConfigAction::Migrate) {
+            warn_old_layout(&config, &config_path);
+        }
+        let database_path = storage::database_path(cli.data_dir.as_deref())?;
+        return run_config(&config, &config_path, &database_path, action);
+    }
+    let mut ctx = AppContext::load(cli.config_file.as_deref(), cli.data_dir.as_deref())
+        .context("failed to initialize application state")?;
+    ctx.prefer_env = cli.prefer_env;
+    ctx.offline = cli.offline;
+    warn_old_layout(&ctx.config, &ctx.config_path);
 
     match cli.command {
         Command::Sync(args) => run_sync(&mut ctx, args).await?,
-        Command::Init(args) => handle_init(&mut ctx, args)?,
-        Command::Issue { action } => run_issue(&mut ctx, action).await?,
+        Command::Init(args) => handle_init(&mut ctx, args).await?,
+        Command::Issue { action } => run_issue(&mut ctx, action, cli.format).await?,
         Command::Repo { action } => run_repo(&mut ctx, action).await?,
-        Command::Note { action } => match action {
-            NoteAction::Add { number, text } => {
-                println!("[todo] add note to issue #{number}: {text}");
-            }
-            NoteAction::List { number } => println!("[todo] list notes for issue #{number}"),
-        },
+        Command::Note { action } => run_note(&mut ctx, action)?,
+        Command::Whoami => run_whoami(&mut ctx).await?,
+        Command::Ratelimit => run_ratelimit(&mut ctx).await?,
+        Command::MigrateLabels => run_migrate_labels(&mut ctx)?,
+        Command::Stats { per_repo } => run_stats(&ctx, per_repo)?,
+        Command::Db { action } => run_db(&ctx, action)?,
+        Command::Export { repo, out } => run_export(&ctx, repo, out, cli.format)?,
+        Command::Import { file } => run_import(&mut ctx, &file)?,
+        Command::Cache { action } => run_cache(&mut ctx, action).await?,
+        Command::Completions { .. } => unreachable!("completions are written before loading"),
+        Command::Doctor { .. } => unreachable!("the doctor runs before loading"),
+        Command::Config { .. } => unreachable!("config commands run before loading"),
     }
 
     Ok(())
 }
 
-fn handle_init(ctx: &mut AppContext, args: InitArgs) -> Result<()> {
+async fn handle_init(ctx: &mut AppContext, args: InitArgs) -> Result<()> {
     let mut changed = false;
 
+    if args.clear_token {
+        if ctx.config.token_in_keyring() {
+            config::keyring_delete()?;
+        }
+        if ctx.config.github_token.take().is_some() {
+            println!("Removed the stored GitHub token");
+            changed = true;
+        } else {
+            println!("No GitHub token was stored");
+        }
+    }
+
+    if let Some(base_url) = args.base_url {
+        let base_url = Config::normalize_base_url(&base_url)?;
+        println!("Using GitHub API at {base_url}");
+        ctx.config.github_base_url = Some(base_url);
+        changed = true;
+    }
+
     if let Some(token) = args.token {
-        ctx.config.github_token = Some(token);
+        if !args.no_verify {
+            ctx.ensure_online("verifying the token (pass --no-verify to skip it)")?;
+            let login = github::verify_token(&token, ctx.config.github_base_url.as_deref())
+                .await
+                .context("token not saved; fix it or pass --no-verify to store it unchecked")?;
+            println!("Authenticated as {login}");
+        }
+        if args.use_keyring {
+            config::keyring_store(&token)?;
+            println!("Stored the GitHub token in the OS keyring");
+            ctx.config.github_token = Some(config::KEYRING_TOKEN.to_string());
+        } else {
+            if ctx.config.token_in_keyring()
+                && let Err(err) = config::keyring_delete()
+            {
+                println!(
+                    "Warning: could not remove the previous token from the OS keyring: {err:#}"
+                );
+            }
+            ctx.config.github_token = Some(token);
+        }
         changed = true;
     }
 
@@ -211,168 +994,2397 @@ fn handle_init(ctx: &mut AppContext, args: InitArgs) -> Result<()> {
     Ok(())
 }
 
-async fn run_sync(ctx: &mut AppContext, args: SyncArgs) -> Result<()> {
-    let token = get_token(&ctx.config)?;
-    let repos = resolve_repos(&ctx.config, &args.repo, false, args.repo.is_empty())?;
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum SyncStatus {
+    Succeeded,
+    Failed,
+    Skipped,
+}
 
-    for repo in repos {
-        println!("Syncing {repo}...");
-        let spec = RepoSpec::parse(&repo)?;
-        let client = GithubClient::new(token, spec).await?;
-        let issues = client.list_issues_all().await?;
-        for issue in &issues {
-            ctx.storage.upsert_issue(&repo, issue)?;
+impl SyncStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SyncStatus::Succeeded => "succeeded",
+            SyncStatus::Failed => "failed",
+            SyncStatus::Skipped => "skipped",
         }
-        println!("  cached {} issues", issues.len());
     }
+}
 
-    Ok(())
+#[derive(Serialize)]
+struct SyncReportEntry {
+    repo: String,
+    status: SyncStatus,
+    issues_cached: usize,
+    error: Option<String>,
+    duration_secs: f64,
 }
 
-async fn run_issue(ctx: &mut AppContext, action: IssueAction) -> Result<()> {
-    let token = get_token(&ctx.config)?;
+async fn run_whoami(ctx: &mut AppContext) -> Result<()> {
+    if ctx.offline
+        && let Some(login) = &ctx.config.github_login
+    {
+        println!("Login: {login} (last known; offline mode is on)");
+        return Ok(());
+    }
+    ctx.ensure_online("`notehub whoami`")?;
+    let token = &get_token(ctx)?;
+    let identity = github::current_user(token, ctx.config.github_base_url.as_deref()).await?;
+    println!("Login: {}", identity.login);
+    if let Some(name) = &identity.name {
+        println!("Name:  {name}");
+    }
+    println!(
+        "API:   {}",
+        github::api_base_url(ctx.config.github_base_url.as_deref())
+    );
 
-    match action {
-        IssueAction::List { repo, all } => {
-            let repos = resolve_repos(&ctx.config, &repo, repo.is_empty() && !all, all)?;
-            for (idx, repo_name) in repos.iter().enumerate() {
-                let issues = ctx.storage.list_issues(repo_name)?;
-                if repos.len() > 1 {
-                    if idx > 0 {
-                        println!();
-                    }
-                    println!("Repository: {repo_name}");
-                }
-                if issues.is_empty() {
-                    println!("  (no cached issues)");
-                } else {
-                    for issue in issues {
-                        println!("#{:<6} {}", issue.number, issue.title);
-                    }
-                }
-            }
-        }
-        IssueAction::View { number, repo } => {
-            let repo_name = resolve_single_repo(&ctx.config, repo.as_deref())?;
-            if let Some(issue) = ctx.storage.get_issue(&repo_name, number)? {
-                print_issue_detail(issue);
-            } else {
-                println!("Issue not cached locally. Fetching from GitHub...");
-                let spec = RepoSpec::parse(&repo_name)?;
-                let client = GithubClient::new(token, spec).await?;
-                let issue = client.get_issue(number).await?;
-                ctx.storage.upsert_issue(&repo_name, &issue)?;
-                if let Some(detail) = ctx.storage.get_issue(&repo_name, number)? {
-                    print_issue_detail(detail);
-                }
-            }
-        }
+    if ctx.config.github_login.as_ref() != Some(&identity.login) {
+        ctx.config.github_login = Some(identity.login);
+        ctx.save()?;
     }
+    Ok(())
+}
+
+/// Core limit GitHub grants requests without a valid token.
+const UNAUTHENTICATED_CORE_LIMIT: usize = 60;
 
+async fn run_ratelimit(ctx: &mut AppContext) -> Result<()> {
+    ctx.ensure_online("`notehub ratelimit`")?;
+    let token = &get_token(ctx)?;
+    let limits = github::rate_limit(token, ctx.config.github_base_url.as_deref()).await?;
+    for (name, rate) in [
+        ("core", &limits.resources.core),
+        ("search", &limits.resources.search),
+    ] {
+        let reset = DateTime::<Utc>::from_timestamp(rate.reset as i64, 0)
+            .map(|reset| {
+                reset
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_else(|| "unknown".to_string());
+        println!(
+            "{name:<7} {:>5} of {:>5} remaining, resets {reset}",
+            rate.remaining, rate.limit
+        );
+    }
+    if limits.resources.core.limit <= UNAUTHENTICATED_CORE_LIMIT {
+        println!(
+            "Warning: these are the unauthenticated limits; the token may be invalid or lack access. \
+             Check it with `notehub whoami`."
+        );
+    }
     Ok(())
 }
 
-async fn run_repo(ctx: &mut AppContext, action: RepoAction) -> Result<()> {
-    match action {
-        RepoAction::List => {
-            if ctx.config.repos().is_empty() {
-                println!(
-                    "No repositories configured. Use `notehub repo add owner/name` to add one."
+/// Prints one `notehub doctor` line and counts it when it failed.
+fn doctor_line(failures: &mut usize, outcome: Result<String>, label: &str) {
+    match outcome {
+        Ok(detail) => println!("[ok]   {label}: {detail}"),
+        Err(err) => {
+            *failures += 1;
+            let message = github::describe_error(&err);
+            // GitHub errors append a documentation URL on a second line.
+            let message = message.lines().next().unwrap_or_default();
+            println!("[FAIL] {label}: {message}");
+        }
+    }
+}
+
+async fn run_doctor(cli: &Cli, online: bool) -> Result<()> {
+    let mut failures = 0;
+    let config_path = match &cli.config_file {
+        Some(path) => path.clone(),
+        None => config::config_path()?,
+    };
+    // A missing config still lets the token come from `GITHUB_TOKEN`; a broken one stops there.
+    let config = if config_path.exists() {
+        match Config::load(Some(&config_path)) {
+            Ok((config, _)) => {
+                doctor_line(
+                    &mut failures,
+                    Ok(config_path.display().to_string()),
+                    "config",
                 );
-            } else {
-                let active = ctx.config.active_repo();
-                for repo in ctx.config.repos() {
-                    if Some(repo) == active {
-                        println!("* {repo} (active)");
+                Some(config)
+            }
+            Err(err) => {
+                doctor_line(&mut failures, Err(err), "config");
+                None
+            }
+        }
+    } else {
+        doctor_line(
+            &mut failures,
+            Err(anyhow!(
+                "{} does not exist; run `notehub init`",
+                config_path.display()
+            )),
+            "config",
+        );
+        Some(Config::default())
+    };
+
+    let database_path = storage::database_path(cli.data_dir.as_deref())?;
+    if !database_path.exists() {
+        doctor_line(
+            &mut failures,
+            Err(anyhow!(
+                "{} does not exist; run `notehub sync`",
+                database_path.display()
+            )),
+            "database",
+        );
+    } else {
+        // Neither connection migrates, so the cache is reported as found rather than upgraded.
+        match Storage::open_read_only(cli.data_dir.as_deref()) {
+            Ok(storage) => {
+                doctor_line(
+                    &mut failures,
+                    Ok(database_path.display().to_string()),
+                    "database",
+                );
+                let integrity = Storage::open_unmigrated(cli.data_dir.as_deref())
+                    .and_then(|storage| storage.integrity_problems())
+                    .and_then(|problems| {
+                        ensure!(problems.is_empty(), "{}", problems.join("; "));
+                        Ok("ok".to_string())
+                    });
+                doctor_line(&mut failures, integrity, "integrity check");
+                let version = storage.schema_version().and_then(|version| {
+                    ensure!(
+                        version <= storage::SCHEMA_VERSION,
+                        "{version} is newer than this build supports ({}); upgrade notehub",
+                        storage::SCHEMA_VERSION
+                    );
+                    Ok(if version < storage::SCHEMA_VERSION {
+                        format!(
+                            "{version}, needs upgrade to {} (applied by the next command that opens the cache)",
+                            storage::SCHEMA_VERSION
+                        )
                     } else {
-                        println!("  {repo}");
-                    }
-                }
+                        format!("{version}, current")
+                    })
+                });
+                doctor_line(&mut failures, version, "schema version");
             }
+            Err(err) => doctor_line(&mut failures, Err(err), "database"),
         }
-        RepoAction::Add { repo, set_active } => {
-            let (normalized, added) = ctx.config.add_repo(&repo)?;
-            if added {
-                println!("Added {normalized}");
-            } else {
-                println!("Repository {normalized} already exists");
+    }
+
+    if let Some(config) = config {
+        let token = match resolve_token(&config, cli.prefer_env) {
+            Ok(token) => {
+                doctor_line(&mut failures, Ok("present".to_string()), "token");
+                Some(token)
             }
-            if set_active || ctx.config.active_repo().is_none() {
-                let active = ctx.config.set_active_repo(&normalized)?;
-                println!("Active repository: {active}");
+            Err(err) => {
+                doctor_line(&mut failures, Err(err), "token");
+                None
             }
-            ctx.save()?;
+        };
+        let repos = config.repos();
+        if repos.is_empty() {
+            doctor_line(
+                &mut failures,
+                Err(anyhow!("none configured; add one with `notehub repo add`")),
+                "repositories",
+            );
         }
-        RepoAction::AddAll { exclude } => {
-            let token = get_token(&ctx.config)?;
-            let mut exclude_set = HashSet::new();
-            for repo in exclude {
+        match token {
+            Some(token) if online => {
+                let base_url = config.github_base_url.as_deref();
+                let login = github::verify_token(&token, base_url)
+                    .await
+                    .map(|login| format!("authenticates as {login}"));
+                doctor_line(&mut failures, login, "token validity");
+                for repo in repos {
+                    let reachable = async {
+                        GithubClient::new(&token, base_url, RepoSpec::parse(repo)?)
+                            .await?
+                            .check_access()
+                            .await?;
+                        Ok("reachable".to_string())
+                    };
+                    doctor_line(&mut failures, reachable.await, repo);
+                }
+            }
+            _ => {
+                if token.is_some() {
+                    println!("[skip] token validity: pass --online to check it");
+                }
+                for repo in repos {
+                    println!("[skip] {repo}: pass --online to check it is reachable");
+                }
+            }
+        }
+    }
+
+    if failures > 0 {
+        bail!("{} failed", pluralize(failures as i64, "check", "checks"));
+    }
+    println!("All checks passed.");
+    Ok(())
+}
+
+/// Returns the configured login, asking GitHub once (and remembering the answer) if unknown.
+async fn my_login(ctx: &mut AppContext) -> Result<String> {
+    if let Some(login) = &ctx.config.github_login {
+        return Ok(login.clone());
+    }
+    ctx.ensure_online("looking up your login")?;
+    let identity =
+        github::current_user(&get_token(ctx)?, ctx.config.github_base_url.as_deref()).await?;
+    ctx.config.github_login = Some(identity.login.clone());
+    ctx.save()?;
+    Ok(identity.login)
+}
+
+async fn run_sync(ctx: &mut AppContext, args: SyncArgs) -> Result<()> {
+    ctx.ensure_online("`notehub sync`")?;
+    let token = &get_token(ctx)?;
+    let max_body_size = args.max_body_size.or(ctx.config.max_body_size);
+    let (run_id, repos) = if args.resume {
+        let Some(run) = ctx.storage.latest_incomplete_sync_run()? else {
+            println!("No interrupted sync to resume.");
+            return Ok(());
+        };
+        println!(
+            "Resuming sync run {} ({} of {} repositories already done)",
+            run.run_id,
+            run.total - run.remaining.len(),
+            run.total
+        );
+        // A dry run previews what would be resumed without marking anything done.
+        ((!args.dry_run).then_some(run.run_id), run.remaining)
+    } else {
+        let repos = resolve_repos(&ctx.config, &args.repo, false, args.repo.is_empty())?;
+        if args.dry_run {
+            (None, repos)
+        } else {
+            (Some(ctx.storage.begin_sync_run(&repos)?), repos)
+        }
+    };
+
+    let octo = github::build_client(token, ctx.config.github_base_url.as_deref())?;
+    let plans = repos
+        .into_iter()
+        .map(|repo| plan_sync(ctx, repo, &args))
+        .collect::<Result<Vec<_>>>()?;
+    let mut report = Vec::new();
+    let total = plans.len();
+    let progress = SyncProgress::new();
+    // Fetches overlap, but `buffered` hands them back in order so each repository's output
+    // and cache writes stay together.
+    let mut fetches = stream::iter(plans)
+        .map(|plan| {
+            let (octo, args, progress) = (&octo, &args, &progress);
+            async move {
+                let started = Instant::now();
+                let fetched = fetch_repo(octo, &plan, args, progress).await;
+                (plan.repo, started, fetched)
+            }
+        })
+        .buffered(usize::from(args.jobs));
+    while let Some((repo, started, fetched)) = fetches.next().await {
+        progress.clear();
+        println!("Syncing {repo}...");
+        let outcome = match fetched {
+            Ok(fetched) if args.dry_run => preview_repo(&ctx.storage, &repo, &fetched),
+            Ok(fetched) => {
+                store_repo(
+                    &mut ctx.storage,
+                    &octo,
+                    &repo,
+                    fetched,
+                    &args,
+                    max_body_size,
+                )
+                .await
+            }
+            Err(err) => Err(err),
+        };
+        let (status, issues_cached, error) = match outcome {
+            Ok(count) => (SyncStatus::Succeeded, count, None),
+            Err(err) if github::is_not_found(&err) => {
+                println!("  skipped: repository not found or not accessible");
+                (SyncStatus::Skipped, 0, Some(github::describe_error(&err)))
+            }
+            Err(err) => {
+                let message = github::describe_error(&err);
+                println!("  failed: {message}");
+                (SyncStatus::Failed, 0, Some(message))
+            }
+        };
+        if let Some(run_id) = &run_id {
+            ctx.storage.mark_sync_run(run_id, &repo, status.as_str())?;
+        }
+        report.push(SyncReportEntry {
+            repo,
+            status,
+            issues_cached,
+            error,
+            duration_secs: started.elapsed().as_secs_f64(),
+        });
+        if args.fail_fast && matches!(status, SyncStatus::Failed) {
+            let remaining = total - report.len();
+            if remaining > 0 {
+                let hint = if run_id.is_some() {
+                    " Continue with `notehub sync --resume`."
+                } else {
+                    ""
+                };
+                println!(
+                    "Stopping after the first failure (--fail-fast); {remaining} repository(ies) not synced.{hint}"
+                );
+            }
+            break;
+        }
+    }
+
+    if let Some(path) = &args.report {
+        let raw = serde_json::to_string_pretty(&report).context("failed to encode sync report")?;
+        fs::write(path, raw)
+            .with_context(|| format!("failed to write sync report to {}", path.display()))?;
+        println!("Wrote sync report to {}", path.display());
+    }
+
+    if args.dry_run {
+        println!("Dry run: nothing was written to the cache.");
+    }
+
+    let failed = report
+        .iter()
+        .filter(|entry| matches!(entry.status, SyncStatus::Failed))
+        .count();
+    ensure!(failed == 0, "{failed} repository(ies) failed to sync");
+    Ok(())
+}
+
+/// A running "fetched N issues..." line on stderr while issue pages arrive, so a long sync
+/// does not look frozen. Only drawn when stdout and stderr are terminals.
+struct SyncProgress {
+    enabled: bool,
+    fetched: AtomicUsize,
+}
+
+impl SyncProgress {
+    fn new() -> Self {
+        Self {
+            enabled: io::stdout().is_terminal() && io::stderr().is_terminal(),
+            fetched: AtomicUsize::new(0),
+        }
+    }
+
+    fn add(&self, count: usize) {
+        let fetched = self.fetched.fetch_add(count, Ordering::Relaxed) + count;
+        if self.enabled {
+            eprint!("\r\x1b[2K  fetched {fetched} issues...");
+            let _ = io::stderr().flush();
+        }
+    }
+
+    /// Erases the line so regular output starts on a clean one.
+    fn clear(&self) {
+        if self.enabled && self.fetched.load(Ordering::Relaxed) > 0 {
+            eprint!("\r\x1b[2K");
+            let _ = io::stderr().flush();
+        }
+    }
+}
+
+/// What to fetch for one repository, worked out before any request is sent.
+struct SyncPlan {
+    repo: String,
+    /// Start of the incremental fetch; `None` fetches every issue.
+    since: Option<DateTime<Utc>>,
+    /// Whether the newest fetched `updated_at` may become the next cursor.
+    advance_cursor: bool,
+    etag: Option<String>,
+    with_comments: bool,
+}
+
+/// Fetches start at `--since`, else at the stored cursor; pruning needs the complete issue
+/// list, so it always ignores the cursor.
+fn plan_sync(ctx: &AppContext, repo: String, args: &SyncArgs) -> Result<SyncPlan> {
+    let cursor = ctx
+        .storage
+        .get_sync_cursor(&repo, ISSUES_CURSOR)?
+        .and_then(|cursor| DateTime::parse_from_rfc3339(&cursor).ok())
+        .map(|cursor| cursor.with_timezone(&Utc));
+    let (since, advance_cursor) = match args.since {
+        // Moving the cursor past issues this run skipped would lose them for good.
+        Some(since) => (Some(since), cursor.is_some_and(|cursor| since <= cursor)),
+        None if args.full || args.prune => (None, true),
+        None => (cursor, true),
+    };
+    // A full, pruning or explicitly bounded sync wants the listing even when nothing changed.
+    let etag =
+        if args.full || args.prune || args.since.is_some() || args.include_closed_since.is_some() {
+            None
+        } else {
+            ctx.storage.get_sync_cursor(&repo, ISSUES_ETAG)?
+        };
+    let with_comments = args.with_comments
+        || ctx
+            .config
+            .repo_override(&repo)
+            .and_then(|repo_override| repo_override.sync_with_comments)
+            .unwrap_or(false);
+    Ok(SyncPlan {
+        repo,
+        since,
+        advance_cursor,
+        etag,
+        with_comments,
+    })
+}
+
+/// Everything fetched from GitHub for one repository, before any of it is cached.
+struct FetchedRepo {
+    since: Option<DateTime<Utc>>,
+    issues: Vec<Issue>,
+    /// Newest `updated_at` of the main fetch, stored as the next sync's cursor.
+    cursor: Option<DateTime<Utc>>,
+    /// Issues returned by `--include-closed-since`, already merged into `issues`.
+    closed_since: Option<usize>,
+    /// Comment threads, when comments were requested for this repository.
+    comments: Option<Vec<(u64, Vec<Comment>)>>,
+    /// ETag of the issue listing, stored for the next conditional request.
+    etag: Option<String>,
+    /// GitHub answered 304 Not Modified: nothing changed since the stored ETag.
+    not_modified: bool,
+}
+
+/// The network half of a repository sync; touches no local state so several can run at once.
+async fn fetch_repo(
+    octo: &octocrab::Octocrab,
+    plan: &SyncPlan,
+    args: &SyncArgs,
+    progress: &SyncProgress,
+) -> Result<FetchedRepo> {
+    let since = plan.since;
+    let client = GithubClient::with_client(octo.clone(), RepoSpec::parse(&plan.repo)?)
+        .max_retries(args.max_retries);
+    let listing = client
+        .list_issues_all(
+            usize::from(args.parallel_pages.unwrap_or(1)),
+            since,
+            plan.etag.as_deref(),
+            &|count| progress.add(count),
+        )
+        .await?;
+    let Some(mut issues) = listing.issues else {
+        return Ok(FetchedRepo {
+            since,
+            issues: Vec::new(),
+            cursor: None,
+            closed_since: None,
+            comments: None,
+            etag: listing.etag,
+            not_modified: true,
+        });
+    };
+    let cursor = issues
+        .iter()
+        .map(|issue| issue.updated_at)
+        .max()
+        .filter(|_| plan.advance_cursor);
+    let mut closed_since = None;
+    if let Some(since) = args.include_closed_since {
+        let known = issues
+            .iter()
+            .map(|issue| issue.number)
+            .collect::<HashSet<_>>();
+        let closed = client.list_closed_since(since).await?;
+        closed_since = Some(closed.len());
+        issues.extend(
+            closed
+                .into_iter()
+                .filter(|issue| !known.contains(&issue.number)),
+        );
+    }
+    let mut comments = None;
+    if plan.with_comments && !args.dry_run {
+        let mut threads = Vec::new();
+        for issue in issues.iter().filter(|issue| issue.comments > 0) {
+            threads.push((issue.number, client.list_comments(issue.number).await?));
+        }
+        comments = Some(threads);
+    }
+    Ok(FetchedRepo {
+        since,
+        issues,
+        cursor,
+        closed_since,
+        comments,
+        etag: listing.etag,
+        not_modified: false,
+    })
+}
+
+/// Reports what `store_repo` would change for `fetched` without writing anything.
+fn preview_repo(storage: &Storage, repo: &str, fetched: &FetchedRepo) -> Result<usize> {
+    if fetched.not_modified {
+        println!("  up to date");
+        return Ok(0);
+    }
+    if let Some(since) = fetched.since {
+        println!("  fetched issues updated since {}", since.to_rfc3339());
+    }
+    let (mut created, mut updated, mut unchanged) = (0, 0, 0);
+    for issue in &fetched.issues {
+        match storage.peek_issue_updated_at(repo, issue.number)? {
+            None => created += 1,
+            Some(cached) if cached == issue.updated_at => unchanged += 1,
+            Some(_) => updated += 1,
+        }
+    }
+    println!("  would create {created}, update {updated}, leave {unchanged} unchanged");
+    Ok(fetched.issues.len())
+}
+
+/// The local half of a repository sync: caches what `fetch_repo` returned, refreshes
+/// timelines, then prunes and verifies as requested. A 304 answer writes nothing.
+async fn store_repo(
+    storage: &mut Storage,
+    octo: &octocrab::Octocrab,
+    repo: &str,
+    fetched: FetchedRepo,
+    args: &SyncArgs,
+    max_body_size: Option<usize>,
+) -> Result<usize> {
+    let FetchedRepo {
+        since,
+        issues,
+        cursor,
+        closed_since,
+        comments: fetched_comments,
+        etag,
+        not_modified,
+    } = fetched;
+    if not_modified {
+        println!("  up to date");
+        return Ok(0);
+    }
+    if let Some(since) = since {
+        println!("  fetched issues updated since {}", since.to_rfc3339());
+    }
+    if let (Some(fetched), Some(since)) = (closed_since, args.include_closed_since) {
+        println!(
+            "  fetched {} closed since {}",
+            pluralize(fetched as i64, "issue", "issues"),
+            since.format("%Y-%m-%d")
+        );
+    }
+    let client = GithubClient::with_client(octo.clone(), RepoSpec::parse(repo)?)
+        .max_retries(args.max_retries);
+    let mut timelines = 0usize;
+    for issue in &issues {
+        storage.upsert_issue(repo, issue, max_body_size)?;
+        if args.timeline && !storage.timeline_is_current(repo, issue.number, &issue.updated_at)? {
+            let events = client.list_timeline(issue.number).await?;
+            storage.replace_events(repo, issue.number, &issue.updated_at, &events)?;
+            timelines += 1;
+        }
+    }
+    let mut comments = 0usize;
+    for (number, thread) in fetched_comments.iter().flatten() {
+        for comment in thread {
+            storage.upsert_comment(repo, *number, comment)?;
+            comments += 1;
+        }
+    }
+    println!("  cached {} issues", issues.len());
+    if args.timeline {
+        println!("  refreshed {timelines} timeline(s)");
+    }
+    if fetched_comments.is_some() {
+        println!(
+            "  cached {}",
+            pluralize(comments as i64, "comment", "comments")
+        );
+    }
+    if args.prune {
+        let numbers = issues.iter().map(|issue| issue.number).collect::<Vec<_>>();
+        let outcome = storage.prune_issues(repo, &numbers, args.force_prune_with_notes)?;
+        println!(
+            "  pruned {}",
+            pluralize(outcome.removed.len() as i64, "issue", "issues")
+        );
+        for (number, notes) in outcome.removed.iter().filter(|(_, notes)| *notes > 0) {
+            eprintln!(
+                "  warning: pruned #{number} together with its {}",
+                pluralize(*notes as i64, "note", "notes")
+            );
+        }
+        for (number, notes) in &outcome.spared {
+            println!(
+                "  kept #{number}: {} attached (use --force-prune-with-notes to remove)",
+                pluralize(*notes as i64, "note", "notes")
+            );
+        }
+    }
+    if args.verify_after {
+        let numbers = issues.iter().map(|issue| issue.number).collect::<Vec<_>>();
+        verify_cache(storage, repo, &numbers, since.is_none())?;
+        println!("  verified cache against {} fetched issues", numbers.len());
+    }
+    if let Some(cursor) = cursor {
+        storage.set_sync_cursor(repo, ISSUES_CURSOR, &cursor.to_rfc3339())?;
+    }
+    if let Some(etag) = etag {
+        storage.set_sync_cursor(repo, ISSUES_ETAG, &etag)?;
+    }
+    Ok(issues.len())
+}
+
+/// Fails if the cache for `repo` disagrees with the issue numbers just fetched. The cached
+/// issue count is only compared after a complete (non-incremental) fetch.
+fn verify_cache(storage: &Storage, repo: &str, fetched: &[u64], complete: bool) -> Result<()> {
+    let mut problems = Vec::new();
+    let cached = storage.cached_issue_count(repo)?;
+    if complete && cached != fetched.len() {
+        problems.push(format!(
+            "{cached} issues cached but {} fetched",
+            fetched.len()
+        ));
+    }
+    let missing = storage.issues_missing_meta(repo, fetched)?;
+    if !missing.is_empty() {
+        let numbers = missing
+            .iter()
+            .map(|number| format!("#{number}"))
+            .collect::<Vec<_>>();
+        problems.push(format!("incomplete cache rows for {}", numbers.join(", ")));
+    }
+    ensure!(
+        problems.is_empty(),
+        "verification failed: {}",
+        problems.join("; ")
+    );
+    Ok(())
+}
+
+async fn run_issue(ctx: &mut AppContext, action: IssueAction, format: OutputFormat) -> Result<()> {
+    match action {
+        IssueAction::List {
+            repo,
+            all,
+            min_assignees,
+            max_assignees,
+            verbose,
+            group_by,
+            json_lines,
+            output_template_file,
+            state,
+            since,
+            updated_today,
+            updated_this_week,
+            body_empty,
+            body_nonempty,
+            author,
+            involves,
+            assignee,
+            created_by_me,
+            no_cache_warn,
+            has_reference_to,
+            label,
+            no_label,
+            milestone,
+            kind,
+            sort,
+            reverse,
+            limit,
+            offset,
+        } => {
+            if no_cache_warn && all && ctx.config.repos().is_empty() {
+                return Ok(());
+            }
+            let repos = resolve_repos(&ctx.config, &repo, repo.is_empty() && !all, all)?;
+            let author = if created_by_me {
+                Some(my_login(ctx).await?)
+            } else {
+                author
+            };
+            let assignee = match assignee {
+                Some(login) if login == "@me" => Some(my_login(ctx).await?),
+                other => other,
+            };
+            let list_state = |repo_name: &str| match group_by {
+                Some(GroupBy::StateReason) => Ok(IssueState::Closed),
+                None => default_list_state(&ctx.config, repo_name, state),
+            };
+            let mut filter = IssueFilter {
+                author,
+                updated_since: if updated_today {
+                    Some(Utc::now().date_naive().and_time(NaiveTime::MIN).and_utc())
+                } else if updated_this_week {
+                    Some(Utc::now() - Duration::days(7))
+                } else {
+                    since
+                },
+                involves,
+                assignee,
+                body_empty: match (body_empty, body_nonempty) {
+                    (true, _) => Some(true),
+                    (_, true) => Some(false),
+                    _ => None,
+                },
+                min_assignees,
+                max_assignees,
+                references: has_reference_to,
+                labels: label,
+                unlabeled: no_label,
+                no_milestone: milestone
+                    .as_deref()
+                    .is_some_and(|title| title.eq_ignore_ascii_case("none")),
+                milestone: milestone.filter(|title| !title.eq_ignore_ascii_case("none")),
+                kind: kind.filter().map(str::to_string),
+                order: sort.order(),
+                reverse,
+                limit,
+                offset,
+                ..IssueFilter::default()
+            };
+            if json_lines {
+                let mut out = io::stdout().lock();
+                for repo_name in &repos {
+                    filter.state = list_state(repo_name)?.filter().map(str::to_string);
+                    ctx.storage.for_each_issue(repo_name, &filter, |issue| {
+                        let line = IssueLine {
+                            repo: repo_name,
+                            issue: &issue,
+                        };
+                        serde_json::to_writer(&mut out, &line)?;
+                        writeln!(out)?;
+                        Ok(())
+                    })?;
+                }
+                return Ok(());
+            }
+            if format == OutputFormat::Json {
+                let mut entries = Vec::new();
+                for repo_name in &repos {
+                    filter.state = list_state(repo_name)?.filter().map(str::to_string);
+                    ctx.storage.for_each_issue(repo_name, &filter, |issue| {
+                        entries.push(IssueListEntry {
+                            repo: repo_name,
+                            number: issue.number,
+                            title: issue.title,
+                            state: issue.state,
+                            comment_count: issue.comment_count,
+                        });
+                        Ok(())
+                    })?;
+                }
+                println!("{}", serde_json::to_string_pretty(&entries)?);
+                return Ok(());
+            }
+            if let Some(path) = output_template_file {
+                let source = fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read template {}", path.display()))?;
+                let mut template = TinyTemplate::new();
+                template.set_default_formatter(&tinytemplate::format_unescaped);
+                template
+                    .add_template("issue", &source)
+                    .with_context(|| format!("invalid template {}", path.display()))?;
+                let mut out = io::stdout().lock();
+                for repo_name in &repos {
+                    filter.state = list_state(repo_name)?.filter().map(str::to_string);
+                    ctx.storage.for_each_issue(repo_name, &filter, |issue| {
+                        let line = IssueLine {
+                            repo: repo_name,
+                            issue: &issue,
+                        };
+                        let rendered = template.render("issue", &line).with_context(|| {
+                            format!("failed to render issue #{} of {repo_name}", issue.number)
+                        })?;
+                        out.write_all(rendered.as_bytes())?;
+                        Ok(())
+                    })?;
+                }
+                return Ok(());
+            }
+            let mut printed = 0usize;
+            for repo_name in &repos {
+                let repo_state = list_state(repo_name)?;
+                filter.state = repo_state.filter().map(str::to_string);
+                let issues = ctx.storage.list_issues(repo_name, &filter)?;
+                if issues.is_empty() && no_cache_warn {
+                    continue;
+                }
+                if repos.len() > 1 {
+                    if printed > 0 {
+                        println!();
+                    }
+                    println!("Repository: {repo_name}");
+                }
+                printed += 1;
+                let paged = limit.is_some() || offset > 0;
+                let total = if paged {
+                    ctx.storage.count_issues(repo_name, &filter)?
+                } else {
+                    issues.len()
+                };
+                if total == 0 {
+                    println!("  (no cached issues)");
+                } else if let Some(GroupBy::StateReason) = group_by {
+                    print_grouped_by_state_reason(&issues, verbose);
+                } else {
+                    for issue in &issues {
+                        print_issue_summary(issue, verbose, repo_state == IssueState::All);
+                    }
+                }
+                if paged && total > 0 {
+                    if issues.is_empty() {
+                        println!("(offset {offset} is past all {total} matching issues)");
+                    } else {
+                        println!(
+                            "showing {}-{} of {total}",
+                            offset as usize + 1,
+                            offset as usize + issues.len()
+                        );
+                    }
+                }
+            }
+        }
+        IssueAction::Open {
+            number,
+            repo,
+            print_url,
+        } => {
+            let repo_name = resolve_single_repo(&ctx.config, repo.as_deref())?;
+            ensure!(
+                ctx.storage.get_issue(&repo_name, number)?.is_some(),
+                "{repo_name}#{number} is not cached; run `notehub sync` or `notehub issue view {number}` first"
+            );
+            let url = github::issue_url(ctx.config.github_base_url.as_deref(), &repo_name, number);
+            if print_url {
+                println!("{url}");
+            } else {
+                println!("Opening {url}");
+                open_in_browser(&url)?;
+            }
+        }
+        IssueAction::View {
+            numbers,
+            repo,
+            timeline,
+            fetch,
+            at,
+            width,
+            no_wrap,
+            open_anchor,
+            include_pr_status,
+            copy,
+            thread,
+            raw,
+        } => {
+            let repo_name = resolve_single_repo(&ctx.config, repo.as_deref())?;
+            ensure!(
+                numbers.len() == 1 || (open_anchor.is_none() && copy.is_none() && !raw),
+                "--open-anchor, --copy and --raw take a single issue number"
+            );
+            if let Some(note_id) = open_anchor {
+                let number = numbers[0];
+                let note = ctx
+                    .storage
+                    .note_anchor(note_id)?
+                    .with_context(|| format!("note {note_id} not found"))?;
+                ensure!(
+                    note.repo == repo_name && note.number == number as i64,
+                    "note {note_id} belongs to {}#{}, not {repo_name}#{number}",
+                    note.repo,
+                    note.number
+                );
+                let anchor = note
+                    .anchor
+                    .with_context(|| format!("note {note_id} has no anchor"))?;
+                let url = github::anchor_url(
+                    ctx.config.github_base_url.as_deref(),
+                    &repo_name,
+                    note.number,
+                    &anchor,
+                )?;
+                println!("Opening {url}");
+                open_in_browser(&url)?;
+                return Ok(());
+            }
+            let width = if no_wrap {
+                None
+            } else {
+                Some(width.unwrap_or_else(textwrap::termwidth))
+            };
+            if fetch {
+                ctx.ensure_online("--fetch")?;
+            }
+            if include_pr_status {
+                ctx.ensure_online("--include-pr-status")?;
+            }
+            // One client serves every number, created the first time GitHub is needed.
+            let mut client = None;
+            let mut json_views = Vec::new();
+            for (index, &number) in numbers.iter().enumerate() {
+                if index > 0 && format == OutputFormat::Text {
+                    println!("\n{}\n", "─".repeat(width.unwrap_or(80).min(80)));
+                }
+                let detail = if fetch {
+                    let client = github_client(ctx, &mut client, &repo_name).await?;
+                    let issue = client.get_issue(number).await?;
+                    let assignees = storage::issue_assignees(&issue);
+                    Some(StoredIssueDetail {
+                        number: issue.number as i64,
+                        title: issue.title,
+                        body: issue.body,
+                        updated_at: issue.updated_at,
+                        state: Some(match issue.state {
+                            octocrab::models::IssueState::Closed => "closed".to_string(),
+                            _ => "open".to_string(),
+                        }),
+                        assignees,
+                        labels: issue.labels.into_iter().map(|label| label.name).collect(),
+                        author: Some(issue.user.login),
+                        created_at: Some(issue.created_at),
+                        original_body_length: None,
+                        is_pull_request: Some(issue.pull_request.is_some()),
+                        triage_state: None,
+                        triage_reason: None,
+                        milestone: issue.milestone.map(|milestone| milestone.title),
+                    })
+                } else if let Some(issue) = ctx.storage.get_issue(&repo_name, number)? {
+                    Some(issue)
+                } else {
+                    ensure!(
+                        !ctx.offline,
+                        "{repo_name}#{number} is not cached and offline mode is on"
+                    );
+                    if format == OutputFormat::Text && !raw {
+                        if numbers.len() > 1 {
+                            println!("Issue #{number} not cached locally. Fetching from GitHub...");
+                        } else {
+                            println!("Issue not cached locally. Fetching from GitHub...");
+                        }
+                    }
+                    let issue = github_client(ctx, &mut client, &repo_name)
+                        .await?
+                        .get_issue(number)
+                        .await?;
+                    ctx.storage
+                        .upsert_issue(&repo_name, &issue, ctx.config.max_body_size)?;
+                    ctx.storage.get_issue(&repo_name, number)?
+                };
+                let detail = match (at, detail) {
+                    (Some(at), Some(mut detail)) => {
+                        let mut versions = ctx.storage.issue_versions(&repo_name, number)?;
+                        let index = match versions
+                            .iter()
+                            .rposition(|version| version.updated_at <= at)
+                        {
+                            Some(index) => index,
+                            None => {
+                                eprintln!(
+                                    "No stored version as old as {}; showing the earliest available.",
+                                    at.to_rfc3339()
+                                );
+                                0
+                            }
+                        };
+                        if index + 1 < versions.len() {
+                            let version = versions.swap_remove(index);
+                            detail.title = version.title;
+                            detail.body = version.body;
+                            detail.updated_at = version.updated_at;
+                            detail.original_body_length = None;
+                        }
+                        Some(detail)
+                    }
+                    (_, detail) => detail,
+                };
+                if raw {
+                    let detail =
+                        detail.with_context(|| format!("{repo_name}#{number} not found"))?;
+                    if let Some(body) = detail.body {
+                        let mut out = io::stdout().lock();
+                        out.write_all(body.as_bytes())?;
+                        out.flush()?;
+                    }
+                    return Ok(());
+                }
+                if format == OutputFormat::Json {
+                    let detail =
+                        detail.with_context(|| format!("{repo_name}#{number} not found"))?;
+                    json_views.push(detail);
+                    continue;
+                }
+                let is_pull_request = detail.as_ref().and_then(|detail| detail.is_pull_request);
+                let clipboard_text = match (copy, &detail) {
+                    (Some(part), Some(detail)) => Some(part.extract(
+                        ctx.config.github_base_url.as_deref(),
+                        &repo_name,
+                        detail,
+                    )),
+                    _ => None,
+                };
+                if let Some(detail) = detail {
+                    print_issue_detail(detail, width);
+                }
+                if !thread && at.is_none() {
+                    print_comments(&ctx.storage, &repo_name, number, width)?;
+                }
+                if let Some(text) = clipboard_text {
+                    copy_to_clipboard(&text);
+                }
+                if include_pr_status {
+                    if is_pull_request == Some(false) {
+                        println!("\nPR: not a pull request");
+                    } else {
+                        let client = github_client(ctx, &mut client, &repo_name).await?;
+                        match client.pull_status(number).await {
+                            Ok(status) => println!("\nPR: {status}"),
+                            Err(err) if github::is_not_found(&err) => {
+                                println!("\nPR: not a pull request")
+                            }
+                            Err(err) => return Err(err),
+                        }
+                    }
+                }
+                if timeline {
+                    print_timeline(&ctx.storage, &repo_name, number)?;
+                }
+                if thread {
+                    print_thread(&ctx.storage, &repo_name, number, width)?;
+                }
+            }
+            if format == OutputFormat::Json {
+                let views = json_views
+                    .iter()
+                    .map(|detail| IssueViewJson {
+                        number: detail.number,
+                        title: &detail.title,
+                        body: detail.body.as_deref(),
+                        updated_at: detail.updated_at.to_rfc3339(),
+                        labels: &detail.labels,
+                        state: detail.state.as_deref(),
+                    })
+                    .collect::<Vec<_>>();
+                // A single number keeps printing a bare object, as before.
+                if let [view] = views.as_slice() {
+                    println!("{}", serde_json::to_string_pretty(view)?);
+                } else {
+                    println!("{}", serde_json::to_string_pretty(&views)?);
+                }
+            }
+        }
+        IssueAction::Search {
+            query,
+            repo,
+            all,
+            highlight_context,
+            limit,
+        } => {
+            let repos = resolve_repos(&ctx.config, &repo, repo.is_empty() && !all, all)?;
+            let hits = ctx
+                .storage
+                .search_issues(&repos, &query, highlight_context, limit)?;
+            if hits.is_empty() {
+                println!("No cached issues match \"{query}\"");
+            }
+            for hit in hits {
+                println!("{}#{:<6} {}", hit.repo, hit.number, hit.title);
+                let snippet = hit.snippet.split_whitespace().collect::<Vec<_>>().join(" ");
+                if !snippet.is_empty() {
+                    println!("    {snippet}");
+                }
+            }
+        }
+        IssueAction::AgeReport { repo, all } => {
+            let repos = resolve_repos(&ctx.config, &repo, repo.is_empty() && !all, all)?;
+            let buckets: [(&str, Option<Duration>); 4] = [
+                ("< 1 week", Some(Duration::weeks(1))),
+                ("1-4 weeks", Some(Duration::weeks(4))),
+                ("1-3 months", Some(Duration::days(90))),
+                ("> 3 months", None),
+            ];
+            let mut counts = [0usize; 4];
+            let mut unknown = 0usize;
+            let now = Utc::now();
+            for repo_name in &repos {
+                for created in ctx.storage.open_issue_created_at(repo_name)? {
+                    let Some(created) = created else {
+                        unknown += 1;
+                        continue;
+                    };
+                    let age = now - created;
+                    let bucket = buckets
+                        .iter()
+                        .position(|(_, limit)| limit.is_none_or(|limit| age < limit))
+                        .unwrap_or(buckets.len() - 1);
+                    counts[bucket] += 1;
+                }
+            }
+
+            let total: usize = counts.iter().sum();
+            let widest = counts.iter().copied().max().unwrap_or_default().max(1);
+            println!("Open issues by age ({total}):");
+            for ((label, _), count) in buckets.iter().zip(counts) {
+                let bar = "#".repeat((count * 40).div_ceil(widest));
+                println!("  {label:<10}  {count:>5}  {bar}");
+            }
+            if unknown > 0 {
+                println!(
+                    "  ({} without a cached creation date; run `notehub sync` to fill them in)",
+                    pluralize(unknown as i64, "issue", "issues")
+                );
+            }
+        }
+        IssueAction::Stats { repo, all } => {
+            let repos = resolve_repos(&ctx.config, &repo, repo.is_empty() && !all, all)?;
+            let cached = ctx.storage.repo_stats()?;
+            let mut rows = repos
+                .iter()
+                .map(|repo_name| {
+                    cached
+                        .iter()
+                        .find(|entry| &entry.repo == repo_name)
+                        .cloned()
+                        .unwrap_or_else(|| RepoStats {
+                            repo: repo_name.clone(),
+                            ..RepoStats::default()
+                        })
+                })
+                .collect::<Vec<_>>();
+            if all {
+                let total = RepoStats {
+                    repo: "TOTAL".to_string(),
+                    issues: rows.iter().map(|entry| entry.issues).sum(),
+                    open: rows.iter().map(|entry| entry.open).sum(),
+                    closed: rows.iter().map(|entry| entry.closed).sum(),
+                    notes: rows.iter().map(|entry| entry.notes).sum(),
+                    first_synced: rows.iter().filter_map(|entry| entry.first_synced).min(),
+                    last_synced: rows.iter().filter_map(|entry| entry.last_synced).max(),
+                };
+                rows.push(total);
+            }
+            let synced = |at: Option<DateTime<Utc>>| {
+                at.map_or_else(
+                    || "never".to_string(),
+                    |at| at.format("%Y-%m-%d %H:%M").to_string(),
+                )
+            };
+            let width = rows
+                .iter()
+                .map(|entry| entry.repo.len())
+                .chain(["REPO".len()])
+                .max()
+                .unwrap_or_default();
+            println!(
+                "{:<width$}  {:>6}  {:>6}  {:>6}  {:<16}  NEWEST SYNC",
+                "REPO", "OPEN", "CLOSED", "NOTES", "OLDEST SYNC"
+            );
+            for entry in &rows {
+                println!(
+                    "{:<width$}  {:>6}  {:>6}  {:>6}  {:<16}  {}",
+                    entry.repo,
+                    entry.open,
+                    entry.closed,
+                    entry.notes,
+                    synced(entry.first_synced),
+                    synced(entry.last_synced)
+                );
+            }
+        }
+        IssueAction::Triage {
+            number,
+            state,
+            note,
+            repo,
+        } => {
+            let repo_name = resolve_single_repo(&ctx.config, repo.as_deref())?;
+            let state = state.trim().to_lowercase();
+            ensure!(!state.is_empty(), "triage state cannot be empty");
+            let reason = note
+                .as_deref()
+                .map(str::trim)
+                .filter(|note| !note.is_empty());
+            ensure!(
+                ctx.storage.set_triage(&repo_name, number, &state, reason)?,
+                "{repo_name}#{number} is not cached"
+            );
+            match reason {
+                Some(_) => {
+                    println!("Triaged {repo_name}#{number} as {state} (reason saved as a note)")
+                }
+                None => println!("Triaged {repo_name}#{number} as {state}"),
+            }
+        }
+        IssueAction::BulkTriage { file, repo } => {
+            let source = fs::read_to_string(&file)
+                .with_context(|| format!("failed to read {}", file.display()))?;
+            let default_repo = match repo {
+                Some(repo) => Some(Config::normalize_repo(&repo)?),
+                None => None,
+            };
+            let mut entries = Vec::new();
+            let mut skipped = 0usize;
+            for (index, line) in source.lines().enumerate() {
+                let line_no = index + 1;
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                let parsed = line
+                    .split_once(char::is_whitespace)
+                    .map(|(reference, state)| (reference, state.trim().to_lowercase()))
+                    .filter(|(_, state)| !state.is_empty());
+                let Some((reference, state)) = parsed else {
+                    println!("line {line_no}: expected `<issue> <state>`, skipped");
+                    skipped += 1;
+                    continue;
+                };
+                let (explicit_repo, number) = match parse_issue_ref(reference) {
+                    Ok(parsed) => parsed,
+                    Err(err) => {
+                        println!("line {line_no}: {err:#}, skipped");
+                        skipped += 1;
+                        continue;
+                    }
+                };
+                let repo_name = match explicit_repo.or_else(|| default_repo.clone()) {
+                    Some(repo_name) => repo_name,
+                    None => {
+                        let mut candidates = Vec::new();
+                        for repo_name in ctx.config.repos() {
+                            if ctx.storage.get_issue(repo_name, number)?.is_some() {
+                                candidates.push(repo_name.clone());
+                            }
+                        }
+                        match candidates.len() {
+                            1 => candidates.remove(0),
+                            0 => {
+                                println!("line {line_no}: #{number} is not cached, skipped");
+                                skipped += 1;
+                                continue;
+                            }
+                            _ => {
+                                println!(
+                                    "line {line_no}: #{number} is ambiguous ({}), skipped; use owner/name#{number}",
+                                    candidates.join(", ")
+                                );
+                                skipped += 1;
+                                continue;
+                            }
+                        }
+                    }
+                };
+                entries.push((line_no, (repo_name, number, state)));
+            }
+
+            let batch = entries
+                .iter()
+                .map(|(_, entry)| entry.clone())
+                .collect::<Vec<_>>();
+            let applied = ctx.storage.set_triage_batch(&batch)?;
+            let mut triaged = 0usize;
+            for ((line_no, (repo_name, number, state)), applied) in entries.iter().zip(applied) {
+                if applied {
+                    println!("{repo_name}#{number} → {state}");
+                    triaged += 1;
+                } else {
+                    println!("line {line_no}: {repo_name}#{number} is not cached, skipped");
+                    skipped += 1;
+                }
+            }
+            println!(
+                "Triaged {}, skipped {skipped}",
+                pluralize(triaged as i64, "issue", "issues")
+            );
+        }
+        IssueAction::Touch { number, repo } => {
+            let repo_name = resolve_single_repo(&ctx.config, repo.as_deref())?;
+            let (previous, now) = ctx
+                .storage
+                .touch_issue(&repo_name, number)?
+                .with_context(|| format!("{repo_name}#{number} is not cached"))?;
+            println!(
+                "Touched {repo_name}#{number}: synced_at {} -> {}",
+                previous.to_rfc3339(),
+                now.to_rfc3339()
+            );
+        }
+        IssueAction::ImportLocal { file, repo } => {
+            let repo_name = resolve_single_repo(&ctx.config, repo.as_deref())?;
+            let source = fs::read_to_string(&file)
+                .with_context(|| format!("failed to read {}", file.display()))?;
+            let (title, body) = parse_draft(&source)
+                .with_context(|| format!("failed to import {}", file.display()))?;
+            let number = ctx.storage.insert_draft(&repo_name, &title, &body)?;
+            println!("Imported draft {number} into {repo_name}: {title}");
+            println!("Publish it with `notehub issue push-draft {number}`");
+        }
+        IssueAction::PushDraft { id, repo } => {
+            let repo_name = resolve_single_repo(&ctx.config, repo.as_deref())?;
+            let number = -id.abs();
+            let draft = ctx
+                .storage
+                .get_draft(&repo_name, number)?
+                .with_context(|| format!("draft {number} not found in {repo_name}"))?;
+            ctx.ensure_online("publishing a draft")?;
+            let spec = RepoSpec::parse(&repo_name)?;
+            let client = GithubClient::new(
+                &get_token(ctx)?,
+                ctx.config.github_base_url.as_deref(),
+                spec,
+            )
+            .await?;
+            let issue = client
+                .create_issue(&draft.title, draft.body.as_deref().unwrap_or_default())
+                .await?;
+            ctx.storage
+                .promote_draft(&repo_name, number, &issue, ctx.config.max_body_size)?;
+            println!(
+                "Created {repo_name}#{} from draft {number}: {}",
+                issue.number, issue.html_url
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Splits a draft into the title from its leading `# ` heading and the body after it.
+fn parse_draft(source: &str) -> Result<(String, String)> {
+    let mut lines = source.lines().skip_while(|line| line.trim().is_empty());
+    let title = lines
+        .next()
+        .and_then(|line| line.trim().strip_prefix("# "))
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+        .context("draft must start with a `# Title` heading")?
+        .to_string();
+    let body = lines.collect::<Vec<_>>().join("\n").trim().to_string();
+    Ok((title, body))
+}
+
+async fn run_repo(ctx: &mut AppContext, action: RepoAction) -> Result<()> {
+    match action {
+        RepoAction::List => {
+            if ctx.config.repos().is_empty() {
+                println!(
+                    "No repositories configured. Use `notehub repo add owner/name` to add one."
+                );
+            } else {
+                let active = ctx.config.active_repo();
+                for repo in ctx.config.repos() {
+                    if Some(repo) == active {
+                        println!("* {repo} (active)");
+                    } else {
+                        println!("  {repo}");
+                    }
+                }
+            }
+        }
+        RepoAction::Add {
+            repo,
+            from_url,
+            set_active,
+        } => {
+            let repo = match (repo, from_url) {
+                (_, Some(url)) => Config::repo_from_url(&url)?,
+                (Some(repo), None) => repo,
+                (None, None) => bail!("Specify a repository or --from-url"),
+            };
+            let (normalized, added) = ctx.config.add_repo(&repo)?;
+            if added {
+                println!("Added {normalized}");
+            } else {
+                println!("Repository {normalized} already exists");
+            }
+            if set_active || ctx.config.active_repo().is_none() {
+                let active = ctx.config.set_active_repo(&normalized)?;
+                println!("Active repository: {active}");
+            }
+            ctx.save()?;
+        }
+        RepoAction::AddAll {
+            exclude,
+            topic,
+            limit,
+            sort,
+        } => {
+            ctx.ensure_online("`notehub repo add-all`")?;
+            let token = &get_token(ctx)?;
+            let mut exclude_set = HashSet::new();
+            for repo in exclude {
                 let normalized = Config::normalize_repo(&repo)?;
                 exclude_set.insert(normalized);
             }
 
-            let repos = github::list_authenticated_repos(token).await?;
-            let mut added = 0usize;
-            let mut skipped_existing = 0usize;
-            let mut skipped_excluded = 0usize;
+            let mut repos =
+                github::list_authenticated_repos(token, ctx.config.github_base_url.as_deref())
+                    .await?;
+            match sort {
+                Some(RepoSort::Pushed) => {
+                    repos.sort_by_key(|repo| std::cmp::Reverse(repo.pushed_at));
+                }
+                Some(RepoSort::Name) => {
+                    repos.sort_by_key(|repo| repo.full_name.to_lowercase());
+                }
+                None => {}
+            }
+            let mut added = 0usize;
+            let mut skipped_existing = 0usize;
+            let mut skipped_excluded = 0usize;
+            let mut skipped_topic = 0usize;
+            let topics = topic
+                .iter()
+                .map(|topic| topic.trim().to_lowercase())
+                .collect::<HashSet<_>>();
+
+            let mut limit_reached = false;
+            for repo in repos {
+                if limit.is_some_and(|limit| added >= limit) {
+                    limit_reached = true;
+                    break;
+                }
+                let normalized = Config::normalize_repo(&repo.full_name)?;
+                if exclude_set.contains(&normalized) {
+                    skipped_excluded += 1;
+                    continue;
+                }
+                if !topics.is_empty()
+                    && !repo
+                        .topics
+                        .iter()
+                        .any(|topic| topics.contains(&topic.to_lowercase()))
+                {
+                    skipped_topic += 1;
+                    continue;
+                }
+                let (_, was_added) = ctx.config.add_repo(&normalized)?;
+                if was_added {
+                    added += 1;
+                } else {
+                    skipped_existing += 1;
+                }
+            }
+
+            ctx.config.ensure_active_repo();
+            ctx.save()?;
+
+            println!("Imported {added} repository(ies)");
+            if skipped_existing > 0 {
+                println!("Skipped {skipped_existing} already configured repositories");
+            }
+            if skipped_excluded > 0 {
+                println!("Skipped {skipped_excluded} excluded repositories");
+            }
+            if skipped_topic > 0 {
+                println!("Skipped {skipped_topic} repositories without a matching topic");
+            }
+            if limit_reached {
+                println!("Stopped at --limit; run again to import more");
+            }
+            if let Some(active) = ctx.config.active_repo() {
+                println!("Active repository: {active}");
+            }
+        }
+        RepoAction::Health { stale_days } => {
+            let stats = ctx.storage.repo_stats()?;
+            let syncs = ctx.storage.last_successful_syncs()?;
+            let width = ctx
+                .config
+                .repos()
+                .iter()
+                .map(String::len)
+                .max()
+                .unwrap_or_default();
+            let stale_after = Duration::days(stale_days);
+            let mut unhealthy = 0usize;
+            for repo in ctx.config.repos() {
+                let cached = stats.iter().find(|entry| &entry.repo == repo);
+                let issues = cached.map_or(0, |entry| entry.issues);
+                let synced = syncs
+                    .iter()
+                    .find(|(name, _)| name == repo)
+                    .map(|(_, at)| *at)
+                    .or_else(|| cached.and_then(|entry| entry.last_synced));
+                let (status, detail) = match synced {
+                    None => ("never synced", "run `notehub sync`".to_string()),
+                    Some(at) if issues == 0 => ("empty", format!("synced {}", format_age(at))),
+                    Some(at) if Utc::now() - at > stale_after => (
+                        "stale",
+                        format!("synced {}, {issues} issues", format_age(at)),
+                    ),
+                    Some(at) => (
+                        "fresh",
+                        format!("synced {}, {issues} issues", format_age(at)),
+                    ),
+                };
+                if status != "fresh" {
+                    unhealthy += 1;
+                }
+                println!("{repo:<width$}  {status:<12}  {detail}");
+            }
+            if ctx.config.repos().is_empty() {
+                println!("No repositories configured.");
+            } else if unhealthy == 0 {
+                println!("All repositories are fresh.");
+            }
+        }
+        RepoAction::Remove { repo } => {
+            let (normalized, removed) = ctx.config.remove_repo(&repo)?;
+            if removed {
+                println!("Removed {normalized}");
+                ctx.save()?;
+            } else {
+                println!("Repository {normalized} not configured");
+            }
+        }
+        RepoAction::Clear { repo, notes } => {
+            let repo = Config::normalize_repo(&repo)?;
+            let outcome = ctx.storage.clear_repo(&repo, notes)?;
+            println!(
+                "Cleared {repo}: removed {} and {}",
+                pluralize(outcome.documents as i64, "cached issue", "cached issues"),
+                pluralize(outcome.notes as i64, "note", "notes")
+            );
+        }
+        RepoAction::Rename { old, new } => {
+            let (old, new) = ctx.config.rename_repo(&old, &new)?;
+            let moved = match ctx.storage.rename_repo(&old, &new) {
+                Ok(moved) => moved,
+                Err(err) => {
+                    ctx.config.rename_repo(&new, &old)?;
+                    return Err(err);
+                }
+            };
+            ctx.save()?;
+            println!(
+                "Renamed {old} to {new} ({} moved)",
+                pluralize(moved as i64, "cached document", "cached documents")
+            );
+        }
+        RepoAction::Use { repo } => {
+            let active = ctx.config.set_active_repo(&repo)?;
+            ctx.save()?;
+            println!("Active repository: {active}");
+        }
+    }
+    Ok(())
+}
+
+/// Word-wraps prose at `width` columns while leaving fenced code blocks untouched.
+fn wrap_body(body: &str, width: usize) -> String {
+    let mut in_fence = false;
+    let mut lines = Vec::new();
+    for line in body.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            lines.push(line.to_string());
+        } else if in_fence || line.len() <= width {
+            lines.push(line.to_string());
+        } else {
+            let indent = &line[..line.len() - trimmed.len()];
+            let options = textwrap::Options::new(width.max(1)).subsequent_indent(indent);
+            lines.push(textwrap::fill(line, options));
+        }
+    }
+    lines.join("\n")
+}
+
+fn run_config(
+    config: &Config,
+    config_path: &Path,
+    database_path: &Path,
+    action: ConfigAction,
+) -> Result<()> {
+    match action {
+        ConfigAction::Migrate => {
+            if !config_path.exists() {
+                println!("No config file at {}", config_path.display());
+                return Ok(());
+            }
+            let changes = Config::migrate_file(config_path)?;
+            if changes.is_empty() {
+                println!("{} is already up to date", config_path.display());
+                return Ok(());
+            }
+            println!("Migrated {}:", config_path.display());
+            for change in &changes {
+                println!("  - {change}");
+            }
+            println!("Original kept at {}.bak", config_path.display());
+        }
+        ConfigAction::Path => {
+            let missing = if config_path.exists() {
+                ""
+            } else {
+                " (not created yet)"
+            };
+            println!("Config:   {}{missing}", config_path.display());
+            println!("Database: {}", database_path.display());
+        }
+        ConfigAction::Show => {
+            if !config_path.exists() {
+                println!(
+                    "# No config file at {} yet; showing defaults",
+                    config_path.display()
+                );
+            }
+            print!("{}", config.redacted_toml()?);
+        }
+    }
+    Ok(())
+}
+
+/// Points at `config migrate` when the config file still uses an old layout.
+fn warn_old_layout(config: &Config, config_path: &Path) {
+    if !config.pending_migrations.is_empty() {
+        eprintln!(
+            "Warning: {} uses an old layout; run `notehub config migrate` to upgrade it",
+            config_path.display()
+        );
+    }
+}
+
+fn run_migrate_labels(ctx: &mut AppContext) -> Result<()> {
+    let migration = ctx.storage.migrate_labels(|done, total| {
+        if done % 500 == 0 || done == total {
+            println!("  {done}/{total} issues");
+        }
+    })?;
+    println!(
+        "Migrated {} across {}",
+        pluralize(migration.labels as i64, "label", "labels"),
+        pluralize(migration.issues as i64, "issue", "issues")
+    );
+    if !migration.unclear.is_empty() {
+        println!("Could not split these cleanly (a label may contain a comma); resync to fix:");
+        for (repo, number, raw) in &migration.unclear {
+            println!("  {repo}#{number}: {raw:?}");
+        }
+    }
+    Ok(())
+}
+
+fn run_stats(ctx: &AppContext, per_repo: bool) -> Result<()> {
+    let mut stats = ctx.storage.repo_stats()?;
+    for repo in ctx.config.repos() {
+        if !stats.iter().any(|entry| &entry.repo == repo) {
+            stats.push(RepoStats {
+                repo: repo.clone(),
+                ..RepoStats::default()
+            });
+        }
+    }
+    stats.sort_by(|a, b| a.repo.cmp(&b.repo));
+    let last_synced = |at: Option<DateTime<Utc>>| {
+        at.map_or_else(
+            || "never".to_string(),
+            |at| at.format("%Y-%m-%d %H:%M").to_string(),
+        )
+    };
+
+    if per_repo {
+        let width = stats
+            .iter()
+            .map(|entry| entry.repo.len())
+            .chain(["REPO".len()])
+            .max()
+            .unwrap_or_default();
+        println!(
+            "{:<width$}  {:>6}  {:>6}  {:>6}  {:>6}  LAST SYNCED",
+            "REPO", "ISSUES", "OPEN", "CLOSED", "NOTES"
+        );
+        for entry in &stats {
+            println!(
+                "{:<width$}  {:>6}  {:>6}  {:>6}  {:>6}  {}",
+                entry.repo,
+                entry.issues,
+                entry.open,
+                entry.closed,
+                entry.notes,
+                last_synced(entry.last_synced)
+            );
+        }
+        return Ok(());
+    }
+
+    let issues: i64 = stats.iter().map(|entry| entry.issues).sum();
+    let open: i64 = stats.iter().map(|entry| entry.open).sum();
+    let closed: i64 = stats.iter().map(|entry| entry.closed).sum();
+    let notes: i64 = stats.iter().map(|entry| entry.notes).sum();
+    let synced = stats.iter().filter_map(|entry| entry.last_synced).max();
+    println!("Repositories: {}", stats.len());
+    println!("Issues:       {issues} ({open} open, {closed} closed)");
+    println!("Notes:        {notes}");
+    println!("Last synced:  {}", last_synced(synced));
+    Ok(())
+}
+
+fn run_import(ctx: &mut AppContext, file: &Path) -> Result<()> {
+    let raw =
+        fs::read_to_string(file).with_context(|| format!("failed to read {}", file.display()))?;
+    let mut archive: Archive = serde_json::from_str(&raw)
+        .with_context(|| format!("{} is not a notehub JSON export", file.display()))?;
+    for archived in &mut archive.repos {
+        archived.repo = Config::normalize_repo(&archived.repo)?;
+    }
+    let summaries = ctx.storage.import_archive(&archive)?;
+
+    let mut added_repos = false;
+    for summary in &summaries {
+        let duplicates = if summary.duplicate_notes > 0 {
+            format!(" ({} already present)", summary.duplicate_notes)
+        } else {
+            String::new()
+        };
+        let stale = if summary.stale_issues > 0 {
+            format!(
+                "; kept {} cached copies that are newer",
+                summary.stale_issues
+            )
+        } else {
+            String::new()
+        };
+        println!(
+            "{}: imported {} and {}{duplicates}{stale}",
+            summary.repo,
+            pluralize(summary.issues as i64, "issue", "issues"),
+            pluralize(summary.notes as i64, "note", "notes")
+        );
+        let (_, added) = ctx.config.add_repo(&summary.repo)?;
+        if added {
+            println!("  added {} to the configured repositories", summary.repo);
+            added_repos = true;
+        }
+    }
+    if added_repos {
+        ctx.config.ensure_active_repo();
+        ctx.save()?;
+    }
+    Ok(())
+}
+
+fn run_export(
+    ctx: &AppContext,
+    repo: Option<String>,
+    out: Option<PathBuf>,
+    format: OutputFormat,
+) -> Result<()> {
+    if format == OutputFormat::Json {
+        let repos = match repo {
+            Some(repo) => vec![resolve_single_repo(&ctx.config, Some(&repo))?],
+            None => ctx
+                .storage
+                .repo_stats()?
+                .into_iter()
+                .map(|stats| stats.repo)
+                .collect(),
+        };
+        let Some(path) = out else {
+            export::export_json(&ctx.storage, &repos, io::stdout().lock())?;
+            return Ok(());
+        };
+        let file = fs::File::create(&path)
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        let count = export::export_json(&ctx.storage, &repos, io::BufWriter::new(file))?;
+        println!(
+            "Exported {} from {} to {}",
+            pluralize(count as i64, "issue", "issues"),
+            pluralize(repos.len() as i64, "repository", "repositories"),
+            path.display()
+        );
+        return Ok(());
+    }
+
+    let out = out.context("--out <dir> is required for the Markdown export")?;
+    let repo = resolve_single_repo(&ctx.config, repo.as_deref())?;
+    let count = export::export_markdown(&ctx.storage, &repo, &out)?;
+    println!(
+        "Exported {} from {repo} to {}",
+        pluralize(count as i64, "issue", "issues"),
+        out.display()
+    );
+    Ok(())
+}
+
+async fn run_cache(ctx: &mut AppContext, action: CacheAction) -> Result<()> {
+    ctx.ensure_online("`notehub cache warm`")?;
+    let token = &get_token(ctx)?;
+    match action {
+        CacheAction::Warm {
+            repo,
+            all,
+            state,
+            label,
+        } => {
+            let repos = resolve_repos(&ctx.config, &repo, repo.is_empty() && !all, all)?;
+            let filter = IssueFilter {
+                state: state.and_then(IssueState::filter).map(str::to_string),
+                labels: label,
+                body_incomplete: true,
+                ..IssueFilter::default()
+            };
+            let mut warmed = 0usize;
+            let mut failed = 0usize;
+            for repo_name in &repos {
+                let issues = ctx.storage.list_issues(repo_name, &filter)?;
+                if issues.is_empty() {
+                    continue;
+                }
+                println!(
+                    "{repo_name}: fetching {}",
+                    pluralize(issues.len() as i64, "issue", "issues")
+                );
+                let client = GithubClient::new(
+                    token,
+                    ctx.config.github_base_url.as_deref(),
+                    RepoSpec::parse(repo_name)?,
+                )
+                .await?;
+                for issue in issues {
+                    match client.get_issue(issue.number as u64).await {
+                        Ok(fetched) => {
+                            ctx.storage.upsert_issue(repo_name, &fetched, None)?;
+                            warmed += 1;
+                        }
+                        Err(err) => {
+                            eprintln!("  #{}: {}", issue.number, github::describe_error(&err));
+                            failed += 1;
+                        }
+                    }
+                }
+            }
+            println!("Warmed {}", pluralize(warmed as i64, "issue", "issues"));
+            ensure!(
+                failed == 0,
+                "{} could not be fetched",
+                pluralize(failed as i64, "issue", "issues")
+            );
+        }
+    }
+    Ok(())
+}
+
+fn run_db(ctx: &AppContext, action: DbAction) -> Result<()> {
+    match action {
+        DbAction::Shell { builtin } => {
+            let path = &ctx.database_path;
+            println!("Database: {}", path.display());
+            println!("Careful: statements that write change your cache directly.");
+            if !builtin {
+                match process::Command::new("sqlite3").arg(path).status() {
+                    Ok(status) => {
+                        ensure!(status.success(), "sqlite3 exited with {status}");
+                        return Ok(());
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                        println!("sqlite3 not found; using the built-in prompt.");
+                    }
+                    Err(err) => return Err(err).context("failed to launch sqlite3"),
+                }
+            }
+            run_sql_prompt(&ctx.storage)?;
+        }
+        DbAction::ExportSql { out, repo } => {
+            let repo = repo.as_deref().map(Config::normalize_repo).transpose()?;
+            let file = fs::File::create(&out)
+                .with_context(|| format!("failed to create {}", out.display()))?;
+            let mut writer = io::BufWriter::new(file);
+            let rows = ctx.storage.export_sql(&mut writer, repo.as_deref())?;
+            writer
+                .flush()
+                .with_context(|| format!("failed to write {}", out.display()))?;
+            println!(
+                "Exported {} to {}",
+                pluralize(rows as i64, "row", "rows"),
+                out.display()
+            );
+        }
+    }
+    Ok(())
+}
 
-            for repo in repos {
-                let normalized = Config::normalize_repo(&repo)?;
-                if exclude_set.contains(&normalized) {
-                    skipped_excluded += 1;
+/// Minimal REPL for `db shell`: one statement per line, `.quit` or EOF to leave.
+fn run_sql_prompt(storage: &Storage) -> Result<()> {
+    println!("Enter one SQL statement per line; `.quit` to exit.");
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        print!("notehub> ");
+        io::stdout().flush()?;
+        line.clear();
+        if stdin.read_line(&mut line)? == 0 {
+            println!();
+            break;
+        }
+        let sql = line.trim().trim_end_matches(';');
+        match sql {
+            "" => continue,
+            ".quit" | ".exit" => break,
+            _ => {}
+        }
+        match storage.is_read_only(sql) {
+            Ok(true) => {}
+            Ok(false) => {
+                print!("This statement modifies the cache. Run it? [y/N] ");
+                io::stdout().flush()?;
+                let mut answer = String::new();
+                stdin.read_line(&mut answer)?;
+                if !answer.trim().eq_ignore_ascii_case("y") {
                     continue;
                 }
-                let (_, was_added) = ctx.config.add_repo(&normalized)?;
-                if was_added {
-                    added += 1;
+            }
+            Err(err) => {
+                println!("Error: {err}");
+                continue;
+            }
+        }
+        match storage.run_query(sql) {
+            Ok(output) if output.columns.is_empty() => {
+                println!(
+                    "{} changed",
+                    pluralize(output.changes as i64, "row", "rows")
+                );
+            }
+            Ok(output) => print_table(&output.columns, &output.rows),
+            Err(err) => println!("Error: {err}"),
+        }
+    }
+    Ok(())
+}
+
+fn print_table(columns: &[String], rows: &[Vec<String>]) {
+    let widths = columns
+        .iter()
+        .enumerate()
+        .map(|(index, column)| {
+            rows.iter()
+                .map(|row| row[index].chars().count())
+                .chain([column.chars().count()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    let render = |cells: &[String]| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    println!("{}", render(columns));
+    for row in rows {
+        println!("{}", render(row));
+    }
+    println!("({})", pluralize(rows.len() as i64, "row", "rows"));
+}
+
+fn run_note(ctx: &mut AppContext, action: NoteAction) -> Result<()> {
+    match action {
+        NoteAction::Add {
+            number,
+            text,
+            repo,
+            refs,
+            anchor,
+        } => {
+            let repo_name = resolve_single_repo(&ctx.config, repo.as_deref())?;
+            let anchor = anchor
+                .as_deref()
+                .map(str::trim)
+                .filter(|anchor| !anchor.is_empty())
+                .map(|anchor| github::Anchor::parse(anchor).map(|anchor| anchor.to_string()))
+                .transpose()?;
+            let id = ctx
+                .storage
+                .add_note(&repo_name, number, &text, anchor.as_deref())?;
+            println!("Added note {id} to {repo_name}#{number}");
+            for target in refs {
+                if ctx.storage.get_issue(&repo_name, target)?.is_none() {
+                    println!(
+                        "Warning: {repo_name}#{target} is not cached; run `notehub sync` to fetch it"
+                    );
+                }
+                ctx.storage.add_note_ref(id, &repo_name, target as i64)?;
+            }
+        }
+        NoteAction::List { number, repo } => {
+            let repo_name = resolve_single_repo(&ctx.config, repo.as_deref())?;
+            let notes = ctx.storage.list_notes(&repo_name, number)?;
+            if notes.is_empty() {
+                println!("(no notes)");
+            }
+            let issue_body = ctx
+                .storage
+                .get_issue(&repo_name, number)?
+                .and_then(|issue| issue.body)
+                .unwrap_or_default();
+            for note in notes {
+                let refs = ctx.storage.note_refs(note.id)?;
+                let edited = if note.updated_at > note.created_at {
+                    format!(", edited {}", format_age(note.updated_at))
                 } else {
-                    skipped_existing += 1;
+                    String::new()
+                };
+                println!(
+                    "[{}] {}{edited}{}",
+                    note.id,
+                    format_age(note.created_at),
+                    format_note_refs(&repo_name, &refs)
+                );
+                if let Some(anchor) = &note.anchor {
+                    match github::Anchor::parse(anchor) {
+                        Ok(github::Anchor::Quote(quote)) => match anchor_line(&issue_body, quote) {
+                            Some(line) => println!("    > \"{quote}\" (body line {line})"),
+                            None => println!("    > \"{quote}\""),
+                        },
+                        _ => println!("    > {anchor}"),
+                    }
+                }
+                for line in note.body.lines() {
+                    println!("    {line}");
                 }
             }
+        }
+        NoteAction::Edit { id, text } => {
+            ensure!(!text.trim().is_empty(), "note text cannot be empty");
+            let old = ctx
+                .storage
+                .note_body(id)?
+                .with_context(|| format!("No note with id {id}"))?;
+            ensure!(ctx.storage.update_note(id, &text)?, "No note with id {id}");
+            println!("Updated note {id}");
+            println!("  old: {}", note_preview(&old));
+            println!("  new: {}", note_preview(&text));
+        }
+        NoteAction::Remove { id } => {
+            ensure!(ctx.storage.delete_note(id)?, "No note with id {id}");
+            println!("Removed note {id}");
+        }
+        NoteAction::History { id } => {
+            let versions = ctx
+                .storage
+                .note_versions(id)?
+                .with_context(|| format!("No note with id {id}"))?;
+            for (idx, version) in versions.iter().enumerate() {
+                if idx > 0 {
+                    println!();
+                }
+                let label = if idx + 1 == versions.len() {
+                    "current".to_string()
+                } else {
+                    format!("revision {}", idx + 1)
+                };
+                println!("== {label} ({})", version.written_at.to_rfc3339());
+                match idx.checked_sub(1).map(|prev| &versions[prev]) {
+                    None => {
+                        for line in version.body.lines() {
+                            println!("  {line}");
+                        }
+                    }
+                    Some(previous) => {
+                        let diff = TextDiff::from_lines(&previous.body, &version.body);
+                        for change in diff.iter_all_changes() {
+                            let sign = match change.tag() {
+                                ChangeTag::Delete => "-",
+                                ChangeTag::Insert => "+",
+                                ChangeTag::Equal => " ",
+                            };
+                            print!("{sign} {change}");
+                            if change.missing_newline() {
+                                println!();
+                            }
+                        }
+                    }
+                }
+            }
+            if versions.len() == 1 {
+                println!("\n(no earlier revisions)");
+            }
+        }
+        NoteAction::Grep { pattern, repo } => {
+            let regex =
+                Regex::new(&pattern).with_context(|| format!("invalid pattern `{pattern}`"))?;
+            let repo = match repo {
+                Some(repo) => Some(resolve_single_repo(&ctx.config, Some(&repo))?),
+                None => None,
+            };
+            let mut matched = 0usize;
+            for note in ctx.storage.notes_with_context(repo.as_deref())? {
+                let lines = note
+                    .body
+                    .lines()
+                    .filter(|line| regex.is_match(line))
+                    .collect::<Vec<_>>();
+                if lines.is_empty() {
+                    continue;
+                }
+                matched += 1;
+                let refs = format_note_refs(&note.repo, &ctx.storage.note_refs(note.id)?);
+                println!("{}#{} (note {}){refs}", note.repo, note.number, note.id);
+                for line in lines {
+                    println!("    {}", line.trim());
+                }
+            }
+            if matched == 0 {
+                println!("No notes match `{pattern}`");
+            }
+        }
+        NoteAction::Search { query, repo } => {
+            let repo = match repo {
+                Some(repo) => Some(resolve_single_repo(&ctx.config, Some(&repo))?),
+                None => None,
+            };
+            let hits = ctx.storage.search_notes(&query, repo.as_deref())?;
+            if hits.is_empty() {
+                println!("No notes match \"{query}\"");
+            }
+            for hit in hits {
+                println!("{}#{} (note {})", hit.repo, hit.number, hit.note_id);
+                let snippet = hit.snippet.split_whitespace().collect::<Vec<_>>().join(" ");
+                if !snippet.is_empty() {
+                    println!("    {snippet}");
+                }
+            }
+        }
+        NoteAction::Stats { repo } => {
+            let repo = match repo {
+                Some(repo) => Some(resolve_single_repo(&ctx.config, Some(&repo))?),
+                None => None,
+            };
+            let notes = ctx.storage.export_notes(repo.as_deref(), None)?;
+            if notes.is_empty() {
+                println!("(no notes)");
+                return Ok(());
+            }
+            let words: usize = notes
+                .iter()
+                .map(|note| note.body.split_whitespace().count())
+                .sum();
+            let mut per_issue: Vec<(&str, i64, usize)> = Vec::new();
+            for note in &notes {
+                match per_issue.last_mut() {
+                    Some((repo, number, count)) if *repo == note.repo && *number == note.number => {
+                        *count += 1
+                    }
+                    _ => per_issue.push((&note.repo, note.number, 1)),
+                }
+            }
+            let now = Utc::now();
+            let added_within = |days: i64| {
+                notes
+                    .iter()
+                    .filter(|note| now - note.created_at <= Duration::days(days))
+                    .count()
+            };
 
-            ctx.config.ensure_active_repo();
-            ctx.save()?;
-
-            println!("Imported {added} repository(ies)");
-            if skipped_existing > 0 {
-                println!("Skipped {skipped_existing} already configured repositories");
+            println!("Notes:          {}", notes.len());
+            println!("Words:          {words}");
+            println!("Average length: {} words", words / notes.len());
+            if let Some((repo, number, count)) = per_issue.iter().max_by_key(|entry| entry.2) {
+                println!(
+                    "Most notes:     {repo}#{number} ({})",
+                    pluralize(*count as i64, "note", "notes")
+                );
             }
-            if skipped_excluded > 0 {
-                println!("Skipped {skipped_excluded} excluded repositories");
+            println!("Last 7 days:    {} added", added_within(7));
+            println!("Last 30 days:   {} added", added_within(30));
+        }
+        NoteAction::Export { since, repo, out } => {
+            let repo = match repo {
+                Some(repo) => Some(resolve_single_repo(&ctx.config, Some(&repo))?),
+                None => None,
+            };
+            let notes = ctx.storage.export_notes(repo.as_deref(), since)?;
+            let digest = render_notes_markdown(&notes, since);
+            match out {
+                Some(path) => {
+                    fs::write(&path, digest)
+                        .with_context(|| format!("failed to write {}", path.display()))?;
+                    println!(
+                        "Exported {} to {}",
+                        pluralize(notes.len() as i64, "note", "notes"),
+                        path.display()
+                    );
+                }
+                None => print!("{digest}"),
             }
-            if let Some(active) = ctx.config.active_repo() {
-                println!("Active repository: {active}");
+        }
+        NoteAction::MoveUp { id } | NoteAction::MoveDown { id } => {
+            let (offset, direction, edge) = match action {
+                NoteAction::MoveUp { .. } => (-1, "up", "first"),
+                _ => (1, "down", "last"),
+            };
+            match ctx.storage.move_note(id, offset)? {
+                None => bail!("No note with id {id}"),
+                Some(true) => println!("Moved note {id} {direction}"),
+                Some(false) => println!("Note {id} is already {edge}"),
             }
         }
-        RepoAction::Remove { repo } => {
-            let (normalized, removed) = ctx.config.remove_repo(&repo)?;
-            if removed {
-                println!("Removed {normalized}");
-                ctx.save()?;
-            } else {
-                println!("Repository {normalized} not configured");
+        NoteAction::Move { id, repo, number } => {
+            let note = ctx
+                .storage
+                .note_anchor(id)?
+                .with_context(|| format!("No note with id {id}"))?;
+            let repo = Config::normalize_repo(&repo)?;
+            let document_id = ctx.storage.document_id(&repo, number)?.with_context(|| {
+                format!(
+                    "issue #{number} is not cached for {repo}; run `notehub sync` or `notehub issue view {number}` first"
+                )
+            })?;
+            ensure!(
+                ctx.storage.reattach_note(id, document_id)?,
+                "No note with id {id}"
+            );
+            println!(
+                "Moved note {id} from {}#{} to {repo}#{number}",
+                note.repo, note.number
+            );
+            let moved_away = (note.repo.as_str(), note.number) != (repo.as_str(), number as i64);
+            if let Some(anchor) = note.anchor.filter(|anchor| anchor.starts_with("comment:"))
+                && moved_away
+            {
+                println!("Dropped its anchor `{anchor}`: that comment belongs to the old issue");
             }
         }
-        RepoAction::Use { repo } => {
-            let active = ctx.config.set_active_repo(&repo)?;
-            ctx.save()?;
-            println!("Active repository: {active}");
+        NoteAction::LinkIssue { id, number, repo } => {
+            let note = ctx
+                .storage
+                .note_anchor(id)?
+                .with_context(|| format!("No note with id {id}"))?;
+            let repo = match repo {
+                Some(repo) => Config::normalize_repo(&repo)?,
+                None => note.repo,
+            };
+            if ctx.storage.get_issue(&repo, number)?.is_none() {
+                println!("Warning: {repo}#{number} is not cached; run `notehub sync` to fetch it");
+            }
+            if ctx.storage.add_note_ref(id, &repo, number as i64)? {
+                println!("Linked note {id} → {repo}#{number}");
+            } else {
+                println!("Note {id} already references {repo}#{number}");
+            }
         }
     }
     Ok(())
 }
 
-fn print_issue_detail(issue: StoredIssueDetail) {
+/// 1-based line of `body` on which an anchor snippet starts, if it appears there.
+fn anchor_line(body: &str, anchor: &str) -> Option<usize> {
+    let offset = body.find(anchor)?;
+    Some(body[..offset].matches('\n').count() + 1)
+}
+
+/// First 60 characters of a note on one line, for confirmations.
+fn note_preview(body: &str) -> String {
+    let flat = body.split_whitespace().collect::<Vec<_>>().join(" ");
+    match flat.char_indices().nth(60) {
+        Some((cut, _)) => format!("{}…", &flat[..cut]),
+        None => flat,
+    }
+}
+
+fn render_notes_markdown(notes: &[ExportedNote], since: Option<DateTime<Utc>>) -> String {
+    let mut out = match since {
+        Some(since) => format!("# Notes since {}\n", since.format("%Y-%m-%d")),
+        None => "# Notes\n".to_string(),
+    };
+    let mut current_issue = None;
+    for note in notes {
+        if current_issue != Some((&note.repo, note.number)) {
+            current_issue = Some((&note.repo, note.number));
+            out.push_str(&format!(
+                "\n## {}#{} — {}\n",
+                note.repo, note.number, note.issue_title
+            ));
+        }
+        let stamp = if note.updated_at > note.created_at {
+            format!("updated {}", note.updated_at.format("%Y-%m-%d %H:%M"))
+        } else {
+            format!("added {}", note.created_at.format("%Y-%m-%d %H:%M"))
+        };
+        out.push_str(&format!(
+            "\n### Note {} ({stamp})\n\n{}\n",
+            note.id,
+            note.body.trim_end()
+        ));
+    }
+    if notes.is_empty() {
+        out.push_str("\n(no notes)\n");
+    }
+    out
+}
+
+/// Renders a note's cross-references as ` → #5, other/repo#7`, or nothing when it has none.
+fn format_note_refs(note_repo: &str, refs: &[(String, i64)]) -> String {
+    if refs.is_empty() {
+        return String::new();
+    }
+    let refs = refs
+        .iter()
+        .map(|(repo, number)| {
+            if repo == note_repo {
+                format!("#{number}")
+            } else {
+                format!("{repo}#{number}")
+            }
+        })
+        .collect::<Vec<_>>();
+    format!(" → {}", refs.join(", "))
+}
+
+fn print_issue_detail(issue: StoredIssueDetail, width: Option<usize>) {
     println!("#{} - {}", issue.number, issue.title);
-    if let Some(body) = issue.body {
-        if !body.trim().is_empty() {
-            println!(
-                "
-{}",
-                body
-            );
+    let author = issue.author.as_deref().unwrap_or("unknown author");
+    match issue.created_at {
+        Some(created_at) => println!(
+            "Opened by {author} on {} ({})",
+            created_at.format("%Y-%m-%d"),
+            format_age(created_at)
+        ),
+        None => println!("Opened by {author}"),
+    }
+    if !issue.assignees.is_empty() {
+        println!("Assigned to {}", issue.assignees.join(", "));
+    }
+    if let Some(milestone) = &issue.milestone {
+        println!("Milestone: {milestone}");
+    }
+    if let Some(state) = &issue.triage_state {
+        match &issue.triage_reason {
+            Some(reason) => println!("Triage: {state} — {}", reason.trim()),
+            None => println!("Triage: {state}"),
         }
     }
+    if let Some(body) = issue.body
+        && !body.trim().is_empty()
+    {
+        let body = match width {
+            Some(width) => wrap_body(&body, width),
+            None => body,
+        };
+        println!(
+            "
+{}",
+            body
+        );
+    }
+    if let Some(length) = issue.original_body_length {
+        println!(
+            "
+(body truncated from {length} bytes; use --fetch to read it in full)"
+        );
+    }
     println!(
         "
 (updated {})",
@@ -380,11 +3392,330 @@ fn print_issue_detail(issue: StoredIssueDetail) {
     );
 }
 
-fn get_token<'a>(config: &'a Config) -> Result<&'a str> {
-    config
-        .github_token
-        .as_deref()
-        .context("GitHub token not configured. Run `notehub init --token ...`.")
+fn print_issue_summary(issue: &StoredIssueSummary, verbose: bool, show_state: bool) {
+    let state = match &issue.state {
+        Some(state) if show_state => format!(" [{state}]"),
+        _ => String::new(),
+    };
+    let labels = if issue.labels.is_empty() {
+        String::new()
+    } else {
+        format!(" · {}", issue.labels.join(", "))
+    };
+    let comments = if issue.comment_count > 0 {
+        format!(
+            " ({})",
+            pluralize(issue.comment_count, "comment", "comments")
+        )
+    } else {
+        String::new()
+    };
+    if verbose {
+        println!(
+            "#{:<6} {}{state}{labels}{comments} ({})",
+            issue.number,
+            issue.title,
+            pluralize(issue.assignee_count, "assignee", "assignees")
+        );
+    } else {
+        println!(
+            "#{:<6} {}{state}{labels}{comments}",
+            issue.number, issue.title
+        );
+    }
+}
+
+fn print_grouped_by_state_reason(issues: &[StoredIssueSummary], verbose: bool) {
+    let groups = [
+        ("Completed", Some("completed")),
+        ("Not planned", Some("not_planned")),
+        ("Closed without a reason", None),
+    ];
+    for (heading, reason) in groups {
+        let members = issues
+            .iter()
+            .filter(|issue| match reason {
+                Some(reason) => issue.state_reason.as_deref() == Some(reason),
+                None => !matches!(
+                    issue.state_reason.as_deref(),
+                    Some("completed" | "not_planned")
+                ),
+            })
+            .collect::<Vec<_>>();
+        if members.is_empty() {
+            continue;
+        }
+        println!("{heading} ({})", members.len());
+        for issue in members {
+            print_issue_summary(issue, verbose, false);
+        }
+    }
+}
+
+/// Prints an issue's cached comments, if any, below its body.
+fn print_comments(storage: &Storage, repo: &str, number: u64, width: Option<usize>) -> Result<()> {
+    let comments = storage.list_comments(repo, number)?;
+    if comments.is_empty() {
+        return Ok(());
+    }
+    println!("\nComments ({}):", comments.len());
+    for comment in comments {
+        println!(
+            "\n@{} {}",
+            comment.author,
+            comment.created_at.format("%Y-%m-%d %H:%M")
+        );
+        let body = comment.body.trim();
+        match width {
+            Some(width) => println!("{}", wrap_body(body, width)),
+            None => println!("{body}"),
+        }
+    }
+    Ok(())
+}
+
+/// Prints cached comments and local notes merged in timestamp order after the issue body.
+fn print_thread(storage: &Storage, repo: &str, number: u64, width: Option<usize>) -> Result<()> {
+    let mut entries = Vec::new();
+    let comments = storage.list_comments(repo, number)?;
+    let comments_cached = !comments.is_empty();
+    for comment in comments {
+        entries.push((
+            comment.created_at,
+            format!("[comment @{}]", comment.author),
+            comment.body,
+        ));
+    }
+    for note in storage.list_notes(repo, number)? {
+        entries.push((note.created_at, format!("[note {}]", note.id), note.body));
+    }
+    entries.sort_by_key(|(at, _, _)| *at);
+
+    if !comments_cached {
+        println!("\n(no cached comments; run `notehub sync --with-comments` to include them)");
+    }
+    for (at, label, body) in entries {
+        println!("\n{label} {}", at.format("%Y-%m-%d %H:%M"));
+        let body = body.trim();
+        if !body.is_empty() {
+            match width {
+                Some(width) => println!("{}", wrap_body(body, width)),
+                None => println!("{body}"),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_timeline(storage: &Storage, repo: &str, number: u64) -> Result<()> {
+    let events = storage.list_events(repo, number)?;
+    println!();
+    if events.is_empty() {
+        println!("(no cached timeline; run `notehub sync --timeline`)");
+        return Ok(());
+    }
+    println!("Timeline:");
+    for event in events {
+        let actor = event
+            .actor
+            .map(|login| format!(" @{login}"))
+            .unwrap_or_default();
+        let detail = event
+            .detail
+            .as_deref()
+            .and_then(|detail| detail.lines().find(|line| !line.trim().is_empty()))
+            .map(|line| format!(" {}", line.trim()))
+            .unwrap_or_default();
+        println!(
+            "  {} {}{}{}",
+            event.created_at.format("%Y-%m-%d %H:%M"),
+            event.kind,
+            actor,
+            detail
+        );
+    }
+    Ok(())
+}
+
+/// Puts `text` on the system clipboard, printing it instead when no clipboard is available
+/// (e.g. over SSH or in a headless session).
+fn copy_to_clipboard(text: &str) {
+    let copied =
+        arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(text.to_string()));
+    match copied {
+        Ok(()) => println!("\nCopied to clipboard"),
+        Err(err) => {
+            println!("\nWarning: clipboard unavailable ({err}); copy it from here instead:");
+            println!("{text}");
+        }
+    }
+}
+
+fn open_in_browser(url: &str) -> Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        process::Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        process::Command::new("xdg-open")
+    };
+    let status = command
+        .arg(url)
+        .status()
+        .context("failed to launch a browser")?;
+    ensure!(status.success(), "browser launcher exited with {status}");
+    Ok(())
+}
+
+/// Describes how long ago `at` was, e.g. `3 days ago`.
+fn format_age(at: DateTime<Utc>) -> String {
+    let elapsed = Utc::now() - at;
+    if elapsed.num_days() > 0 {
+        format!("{} ago", pluralize(elapsed.num_days(), "day", "days"))
+    } else if elapsed.num_hours() > 0 {
+        format!("{} ago", pluralize(elapsed.num_hours(), "hour", "hours"))
+    } else if elapsed.num_minutes() > 0 {
+        format!(
+            "{} ago",
+            pluralize(elapsed.num_minutes(), "minute", "minutes")
+        )
+    } else {
+        "just now".to_string()
+    }
+}
+
+/// Parses `123`, `#123` or `owner/name#123` into an optional repository and issue number.
+fn parse_issue_ref(raw: &str) -> Result<(Option<String>, u64)> {
+    let (repo, number) = match raw.rsplit_once('#') {
+        Some(("", number)) => (None, number),
+        Some((repo, number)) => (
+            Some(
+                Config::normalize_repo(repo)
+                    .with_context(|| format!("invalid issue reference `{raw}`"))?,
+            ),
+            number,
+        ),
+        None => (None, raw),
+    };
+    let number = number
+        .parse()
+        .with_context(|| format!("invalid issue reference `{raw}`"))?;
+    Ok((repo, number))
+}
+
+/// Parses a `--since` style date: `YYYY-MM-DD` (midnight UTC), RFC 3339, or a relative
+/// `<n>d` / `<n>w` / `<n>h` meaning that long ago.
+fn parse_since(raw: &str) -> Result<DateTime<Utc>> {
+    let raw = raw.trim();
+    if let Ok(at) = DateTime::parse_from_rfc3339(raw) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    if let Ok(day) = NaiveDate::parse_from_str(raw, "%Y-%m-%d") {
+        return Ok(day.and_time(NaiveTime::MIN).and_utc());
+    }
+    if let Some((split, _)) = raw.char_indices().last()
+        && let (amount, unit) = raw.split_at(split)
+        && let Ok(amount) = amount.parse::<i64>()
+    {
+        let span = match unit {
+            "h" => Some(Duration::hours(amount)),
+            "d" => Some(Duration::days(amount)),
+            "w" => Some(Duration::weeks(amount)),
+            _ => None,
+        };
+        if let Some(span) = span {
+            return Ok(Utc::now() - span);
+        }
+    }
+    bail!("`{raw}` is not a date (expected YYYY-MM-DD, RFC 3339, or e.g. `7d`)")
+}
+
+fn pluralize(count: i64, singular: &str, plural: &str) -> String {
+    if count == 1 {
+        format!("{count} {singular}")
+    } else {
+        format!("{count} {plural}")
+    }
+}
+
+/// Resolves the GitHub token: the configured one first, then `GITHUB_TOKEN`, or the other way
+/// round with `--prefer-env` / `prefer_env_token = true`. The environment value is never saved.
+fn get_token(ctx: &AppContext) -> Result<String> {
+    resolve_token(&ctx.config, ctx.prefer_env)
+}
+
+/// The client for `repo` kept in `slot`, created on first use so the token is only resolved
+/// when GitHub is actually called.
+async fn github_client<'a>(
+    ctx: &AppContext,
+    slot: &'a mut Option<GithubClient>,
+    repo: &str,
+) -> Result<&'a GithubClient> {
+    match slot {
+        Some(client) => Ok(client),
+        None => {
+            ctx.ensure_online("talking to GitHub")?;
+            let client = GithubClient::new(
+                &get_token(ctx)?,
+                ctx.config.github_base_url.as_deref(),
+                RepoSpec::parse(repo)?,
+            )
+            .await?;
+            Ok(slot.insert(client))
+        }
+    }
+}
+
+fn resolve_token(config: &Config, prefer_env: bool) -> Result<String> {
+    // Only ask the keyring when its token is actually the one that will be used.
+    let configured = || -> Result<Option<String>> {
+        if config.token_in_keyring() {
+            return config::keyring_load().map(Some);
+        }
+        Ok(config
+            .github_token
+            .clone()
+            .filter(|token| !token.is_empty()))
+    };
+    let env = std::env::var("GITHUB_TOKEN")
+        .ok()
+        .filter(|token| !token.trim().is_empty());
+    let token = if prefer_env || config.prefer_env_token {
+        match env {
+            Some(token) => Some(token),
+            None => configured()?,
+        }
+    } else {
+        match configured()? {
+            Some(token) => Some(token),
+            None => env,
+        }
+    };
+    token.context(
+        "GitHub token not configured. Run `notehub init --token ...` or set GITHUB_TOKEN \
+         (the config token wins unless --prefer-env or `prefer_env_token = true` is set).",
+    )
+}
+
+/// `issue list` state for `repo`: `--state` when given, else the repository's `list_state`
+/// override, else open issues.
+fn default_list_state(
+    config: &Config,
+    repo: &str,
+    requested: Option<IssueState>,
+) -> Result<IssueState> {
+    if let Some(state) = requested {
+        return Ok(state);
+    }
+    match config
+        .repo_override(repo)
+        .and_then(|repo_override| repo_override.list_state.as_deref())
+    {
+        Some(state) => IssueState::from_config(state),
+        None => Ok(IssueState::Open),
+    }
 }
 
 fn resolve_single_repo(config: &Config, requested: Option<&str>) -> Result<String> {
@@ -438,3 +3769,187 @@ fn resolve_repos(
 
     bail!("No repositories specified");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issue(number: u64, title: &str) -> Issue {
+        let user = serde_json::json!({
+            "login": "octocat",
+            "id": 1,
+            "node_id": "U_1",
+            "avatar_url": "https://github.com/images/error/octocat_happy.gif",
+            "gravatar_id": "",
+            "url": "https://api.github.com/users/octocat",
+            "html_url": "https://github.com/octocat",
+            "followers_url": "https://api.github.com/users/octocat/followers",
+            "following_url": "https://api.github.com/users/octocat/following",
+            "gists_url": "https://api.github.com/users/octocat/gists",
+            "starred_url": "https://api.github.com/users/octocat/starred",
+            "subscriptions_url": "https://api.github.com/users/octocat/subscriptions",
+            "organizations_url": "https://api.github.com/users/octocat/orgs",
+            "repos_url": "https://api.github.com/users/octocat/repos",
+            "events_url": "https://api.github.com/users/octocat/events",
+            "received_events_url": "https://api.github.com/users/octocat/received_events",
+            "type": "User",
+            "site_admin": false
+        });
+        let url = format!("https://api.github.com/repos/owner/repo/issues/{number}");
+        serde_json::from_value(serde_json::json!({
+            "id": number,
+            "node_id": format!("I_{number}"),
+            "url": url,
+            "repository_url": "https://api.github.com/repos/owner/repo",
+            "labels_url": format!("{url}/labels{{/name}}"),
+            "comments_url": format!("{url}/comments"),
+            "events_url": format!("{url}/events"),
+            "html_url": format!("https://github.com/owner/repo/issues/{number}"),
+            "number": number,
+            "state": "open",
+            "state_reason": null,
+            "title": title,
+            "body": "",
+            "user": user,
+            "labels": [],
+            "assignees": [],
+            "author_association": "OWNER",
+            "locked": false,
+            "comments": 0,
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-02T00:00:00Z"
+        }))
+        .unwrap()
+    }
+
+    fn sync_args(argv: &[&str]) -> SyncArgs {
+        let argv = ["notehub", "sync"].iter().chain(argv);
+        match Cli::parse_from(argv).command {
+            Command::Sync(args) => args,
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn not_modified_answer_writes_nothing_to_the_cache() {
+        let mut storage = Storage::open_in_memory().unwrap();
+        storage
+            .upsert_issue("owner/repo", &issue(1, "cached"), None)
+            .unwrap();
+        let before = storage.get_issue("owner/repo", 1).unwrap().unwrap();
+        let fetched = FetchedRepo {
+            since: None,
+            issues: vec![issue(1, "changed"), issue(2, "new")],
+            cursor: Some(Utc::now()),
+            closed_since: None,
+            comments: None,
+            etag: Some("\"new\"".to_string()),
+            not_modified: true,
+        };
+        let octo = octocrab::Octocrab::builder().build().unwrap();
+
+        let stored = store_repo(
+            &mut storage,
+            &octo,
+            "owner/repo",
+            fetched,
+            &sync_args(&[]),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(stored, 0);
+        assert_eq!(storage.cached_issue_count("owner/repo").unwrap(), 1);
+        let after = storage.get_issue("owner/repo", 1).unwrap().unwrap();
+        assert_eq!(after.title, before.title);
+        assert_eq!(
+            storage.get_sync_cursor("owner/repo", ISSUES_ETAG).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn resolve_repos_treats_casings_as_one_repository() {
+        let mut config = Config::default();
+        config.add_repo("Owner/Repo").unwrap();
+        let repos = resolve_repos(
+            &config,
+            &["Owner/Repo".to_string(), "owner/repo".to_string()],
+            false,
+            false,
+        )
+        .unwrap();
+        assert_eq!(repos, vec!["owner/repo".to_string()]);
+    }
+
+    #[test]
+    fn completion_scripts_cover_every_subcommand_and_flags() {
+        fn names(cmd: &clap::Command, out: &mut Vec<String>) {
+            for sub in cmd.get_subcommands().filter(|sub| !sub.is_hide_set()) {
+                out.push(sub.get_name().to_string());
+                names(sub, out);
+            }
+        }
+        let mut cmd = Cli::command();
+        cmd.build();
+        let mut subcommands = Vec::new();
+        names(&cmd, &mut subcommands);
+        assert!(subcommands.iter().any(|name| name == "sync"));
+
+        for (shell, flag) in [
+            (completions::Shell::Bash, "--dry-run"),
+            (completions::Shell::Zsh, "--dry-run"),
+            (completions::Shell::Fish, "-l dry-run"),
+            (completions::Shell::Powershell, "'--dry-run'"),
+        ] {
+            let mut script = Vec::new();
+            completions::generate(shell, &mut Cli::command(), &mut script).unwrap();
+            let script = String::from_utf8(script).unwrap();
+            for name in &subcommands {
+                assert!(script.contains(name.as_str()), "{name} missing");
+            }
+            assert!(script.contains(flag), "{flag} missing");
+        }
+    }
+
+    #[tokio::test]
+    async fn offline_issue_commands_need_no_token() {
+        let mut ctx = AppContext {
+            config: Config {
+                repos: vec!["owner/repo".to_string()],
+                ..Config::default()
+            },
+            config_path: PathBuf::from("config.toml"),
+            database_path: PathBuf::from("notehub.db"),
+            storage: Storage::open_in_memory().unwrap(),
+            prefer_env: false,
+            offline: true,
+        };
+        ctx.storage
+            .upsert_issue("owner/repo", &issue(1, "cached"), None)
+            .unwrap();
+        for argv in [
+            &["issue", "list", "--repo", "owner/repo"][..],
+            &["issue", "view", "1", "--repo", "owner/repo"],
+        ] {
+            let Command::Issue { action } = Cli::parse_from(["notehub"].iter().chain(argv)).command
+            else {
+                unreachable!()
+            };
+            run_issue(&mut ctx, action, OutputFormat::Text)
+                .await
+                .unwrap();
+        }
+
+        let Command::Issue { action } =
+            Cli::parse_from(["notehub", "issue", "view", "2", "--repo", "owner/repo"]).command
+        else {
+            unreachable!()
+        };
+        let err = run_issue(&mut ctx, action, OutputFormat::Text)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("offline mode is on"), "{err}");
+    }
+}
@@ -1,21 +1,328 @@
-use std::{fs, path::PathBuf};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, ensure};
 use chrono::{DateTime, Utc};
 use octocrab::models::IssueState;
-use octocrab::models::issues::Issue;
-use rusqlite::{Connection, params};
+use octocrab::models::issues::{Comment, Issue, IssueStateReason};
+use octocrab::models::timelines::TimelineEvent;
+use regex::Regex;
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, params, params_from_iter};
+use serde::{Deserialize, Serialize};
 
 const DB_FILE_NAME: &str = "notehub.db";
 
+/// Schema migrations in order: step `i` upgrades a database from version `i` to `i + 1`.
+/// Append new steps for schema changes; never edit one that has shipped.
+const MIGRATIONS: &[fn(&Connection) -> Result<()>] =
+    &[migrate_v1, migrate_v2, migrate_v3, migrate_v4, migrate_v5];
+
+/// Schema version a fully migrated database is at.
+pub const SCHEMA_VERSION: usize = MIGRATIONS.len();
+
+/// Appended to bodies cut down to the configured `max_body_size`.
+const TRUNCATION_MARKER: &str = "... [truncated]";
+
+/// Number of comma-separated logins stored in `issue_meta.assignees`.
+const ASSIGNEE_COUNT_SQL: &str = "CASE WHEN COALESCE(issue_meta.assignees, '') = '' THEN 0
+     ELSE LENGTH(issue_meta.assignees) - LENGTH(REPLACE(issue_meta.assignees, ',', '')) + 1 END";
+
+/// `documents.kind` values of cached GitHub issues and pull requests, for `kind IN ...`.
+const ISSUE_KINDS_SQL: &str = "('issue', 'pull_request')";
+
+/// The body with surrounding whitespace (spaces, tabs, newlines) removed; `''` when missing.
+const BLANK_BODY_SQL: &str =
+    "TRIM(COALESCE(documents.body, ''), ' ' || char(9) || char(10) || char(13))";
+
+/// Display order of notes within an issue: manual `position` first, then chronological.
+const NOTE_ORDER_SQL: &str = "notes.position IS NULL, notes.position, notes.created_at, notes.id";
+
 pub struct Storage {
     conn: Connection,
+    /// Whether the SQLite build supports FTS5; searches fall back to `LIKE` otherwise.
+    fts: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct StoredIssueSummary {
     pub number: i64,
     pub title: String,
+    pub state: Option<String>,
+    pub state_reason: Option<String>,
+    pub assignee_count: i64,
+    pub labels: Vec<String>,
+    pub assignees: Vec<String>,
+    pub author_login: Option<String>,
+    pub updated_at: String,
+    pub comment_count: i64,
+}
+
+#[derive(Debug)]
+pub struct IssueSearchHit {
+    pub repo: String,
+    pub number: i64,
+    pub title: String,
+    pub snippet: String,
+}
+
+#[derive(Debug)]
+pub struct NoteHit {
+    pub repo: String,
+    pub number: i64,
+    pub note_id: i64,
+    pub snippet: String,
+}
+
+#[derive(Debug)]
+pub struct NoteWithContext {
+    pub id: i64,
+    pub repo: String,
+    pub number: i64,
+    pub body: String,
+}
+
+/// Cache totals for one repository, as reported by `notehub stats` and `notehub issue stats`.
+#[derive(Debug, Clone, Default)]
+pub struct RepoStats {
+    pub repo: String,
+    pub issues: i64,
+    pub open: i64,
+    pub closed: i64,
+    pub notes: i64,
+    /// When the least recently synced issue was last synced.
+    pub first_synced: Option<DateTime<Utc>>,
+    pub last_synced: Option<DateTime<Utc>>,
+}
+
+/// A note on one issue, as listed by `notehub note list`.
+#[derive(Debug)]
+pub struct StoredNote {
+    pub id: i64,
+    pub body: String,
+    pub anchor: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The document written by `notehub export --format json` and read by `notehub import`.
+#[derive(Debug, Deserialize)]
+pub struct Archive {
+    pub schema_version: usize,
+    pub repos: Vec<ArchivedRepo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArchivedRepo {
+    pub repo: String,
+    #[serde(default)]
+    pub issues: Vec<ArchivedIssue>,
+}
+
+/// A cached issue with everything stored about it, as written by `notehub export --format json`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchivedIssue {
+    pub number: i64,
+    pub title: String,
+    pub body: Option<String>,
+    pub state: Option<String>,
+    pub state_reason: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub assignees: Vec<String>,
+    pub author: Option<String>,
+    pub created_at: Option<String>,
+    pub updated_at: String,
+    pub is_pull_request: Option<bool>,
+    pub triage_state: Option<String>,
+    /// Absent from archives written before comment counts were cached.
+    #[serde(default)]
+    pub comment_count: i64,
+    /// Absent from archives written before milestones were cached.
+    #[serde(default)]
+    pub milestone: Option<String>,
+    #[serde(default)]
+    pub notes: Vec<ArchivedNote>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchivedNote {
+    pub id: i64,
+    pub anchor: Option<String>,
+    pub body: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// What `Storage::import_archive` did for one repository.
+#[derive(Debug)]
+pub struct ImportSummary {
+    pub repo: String,
+    pub issues: usize,
+    /// Issues left as cached because the cache holds a more recently updated copy.
+    pub stale_issues: usize,
+    pub notes: usize,
+    /// Notes skipped because an identical one (same body and creation time) already exists.
+    pub duplicate_notes: usize,
+}
+
+/// Which `documents.kind` values a lookup by number covers.
+#[derive(Clone, Copy)]
+enum DocumentKinds {
+    Issues,
+    Drafts,
+}
+
+impl DocumentKinds {
+    fn sql(self) -> &'static str {
+        match self {
+            Self::Issues => ISSUE_KINDS_SQL,
+            Self::Drafts => "('draft')",
+        }
+    }
+}
+
+/// A note with the issue it belongs to, as written by `notehub note export`.
+#[derive(Debug)]
+pub struct ExportedNote {
+    pub id: i64,
+    pub repo: String,
+    pub number: i64,
+    pub issue_title: String,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Result of an ad-hoc statement run from `notehub db shell`.
+#[derive(Debug)]
+pub struct QueryOutput {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    /// Rows changed, for statements that return no columns.
+    pub changes: usize,
+}
+
+/// Outcome of [`Storage::migrate_labels`].
+#[derive(Debug, Default)]
+pub struct LabelMigration {
+    pub issues: usize,
+    pub labels: usize,
+    /// `(repo, number, legacy string)` for values that could not be split unambiguously.
+    pub unclear: Vec<(String, i64, String)>,
+}
+
+/// Outcome of pruning issues that upstream no longer returns.
+#[derive(Debug, Default)]
+pub struct PruneOutcome {
+    /// `(number, note count)` for removed issues; the count is only nonzero when forced.
+    pub removed: Vec<(u64, usize)>,
+    /// `(number, note count)` for issues kept because they still carry notes.
+    pub spared: Vec<(u64, usize)>,
+}
+
+/// What `clear_repo` deleted.
+#[derive(Debug, Default)]
+pub struct ClearOutcome {
+    /// Cached issues and pull requests removed.
+    pub documents: usize,
+    /// Notes removed along with them.
+    pub notes: usize,
+}
+
+/// Where a note lives and the location it is anchored to, if any.
+#[derive(Debug)]
+pub struct NoteAnchor {
+    pub repo: String,
+    pub number: i64,
+    pub anchor: Option<String>,
+}
+
+/// One version of a note's text, oldest first in [`Storage::note_versions`].
+#[derive(Debug)]
+pub struct NoteVersion {
+    pub body: String,
+    pub written_at: DateTime<Utc>,
+}
+
+/// One cached version of an issue's title and body, oldest first in [`Storage::issue_versions`].
+#[derive(Debug)]
+pub struct IssueVersion {
+    pub title: String,
+    pub body: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A cached issue comment.
+#[derive(Debug)]
+pub struct StoredComment {
+    pub author: String,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A multi-repo sync that did not finish every repository.
+#[derive(Debug)]
+pub struct IncompleteSyncRun {
+    pub run_id: String,
+    pub total: usize,
+    pub remaining: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct StoredEvent {
+    pub kind: String,
+    pub actor: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub detail: Option<String>,
+}
+
+/// Sort key for issue listings. Numbers and dates sort newest first and titles A to Z, unless
+/// `IssueFilter::reverse` is set.
+#[derive(Debug, Default, Clone, Copy)]
+pub enum IssueOrder {
+    #[default]
+    Number,
+    Updated,
+    Created,
+    Title,
+}
+
+#[derive(Debug, Default)]
+pub struct IssueFilter {
+    pub state: Option<String>,
+    pub author: Option<String>,
+    /// Only issues updated on GitHub at or after this instant.
+    pub updated_since: Option<DateTime<Utc>>,
+    /// Login that authored, is assigned to, or is @-mentioned in the issue.
+    pub involves: Option<String>,
+    /// Login the issue must be assigned to.
+    pub assignee: Option<String>,
+    /// `Some(true)` keeps only issues whose body is missing or whitespace, `Some(false)` the rest.
+    pub body_empty: Option<bool>,
+    pub min_assignees: Option<u32>,
+    pub max_assignees: Option<u32>,
+    /// Only issues whose body references this issue number.
+    pub references: Option<u64>,
+    /// Only issues carrying every one of these labels.
+    pub labels: Vec<String>,
+    /// Only issues without any label.
+    pub unlabeled: bool,
+    /// Only issues in the milestone with this title (case-insensitive).
+    pub milestone: Option<String>,
+    /// Only issues not assigned to any milestone.
+    pub no_milestone: bool,
+    /// Only issues whose cached body is blank or was truncated.
+    pub body_incomplete: bool,
+    /// `issue` or `pull_request`; both when unset.
+    pub kind: Option<String>,
+    pub order: IssueOrder,
+    pub reverse: bool,
+    pub limit: Option<u32>,
+    pub offset: u32,
 }
 
 #[derive(Debug)]
@@ -24,181 +331,2900 @@ pub struct StoredIssueDetail {
     pub title: String,
     pub body: Option<String>,
     pub updated_at: DateTime<Utc>,
+    pub state: Option<String>,
+    pub labels: Vec<String>,
+    pub assignees: Vec<String>,
+    /// Login of whoever opened the issue; unknown for rows cached before this was recorded.
+    pub author: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    /// Size of the upstream body when the cached copy was truncated.
+    pub original_body_length: Option<i64>,
+    /// Whether the entry is a pull request; unknown for rows cached before this was recorded.
+    pub is_pull_request: Option<bool>,
+    /// Local triage state set with `notehub issue triage`.
+    pub triage_state: Option<String>,
+    /// Body of the note recorded with the latest triage change, if any.
+    pub triage_reason: Option<String>,
+    /// Title of the milestone the issue belongs to.
+    pub milestone: Option<String>,
 }
 
 impl Storage {
-    pub fn open() -> Result<Self> {
-        let path = database_path()?;
+    /// Opens (creating if needed) the cache in `data_dir`, or in the default data directory.
+    pub fn open(data_dir: Option<&Path>) -> Result<Self> {
+        let path = database_path(data_dir)?;
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)
                 .with_context(|| format!("failed to create {}", parent.display()))?;
         }
-        let conn = Connection::open(&path)
+        let mut conn = Connection::open(&path)
             .with_context(|| format!("failed to open database at {}", path.display()))?;
         Self::apply_pragmas(&conn)?;
-        Self::migrate(&conn)?;
-        Ok(Self { conn })
+        Self::migrate(&mut conn)?;
+        let fts = Self::create_fts(&conn)?;
+        Ok(Self { conn, fts })
     }
 
-    pub fn upsert_issue(&self, repo: &str, issue: &Issue) -> Result<()> {
-        let external_id = issue.number.to_string();
-        let updated_at = issue.updated_at.clone();
-        let synced_at = Utc::now();
-        let body = issue.body.clone().unwrap_or_default();
+    /// Opens an existing database read-only, exactly as it is on disk: nothing is migrated or
+    /// indexed, so it can be inspected without being upgraded.
+    pub fn open_read_only(data_dir: Option<&Path>) -> Result<Self> {
+        Self::open_existing(data_dir, OpenFlags::SQLITE_OPEN_READ_ONLY)
+    }
 
-        self.conn.execute(
-            "INSERT INTO documents (repo, kind, external_id, title, body, updated_at, synced_at)
-             VALUES (?1, 'issue', ?2, ?3, ?4, ?5, ?6)
-             ON CONFLICT(repo, kind, external_id) DO UPDATE SET
-                 title=excluded.title,
-                 body=excluded.body,
-                 updated_at=excluded.updated_at,
-                 synced_at=excluded.synced_at",
-            params![
-                repo,
-                &external_id,
-                &issue.title,
-                &body,
-                &updated_at.to_rfc3339(),
-                &synced_at.to_rfc3339()
-            ],
-        )?;
+    /// Like `open_read_only`, but writable for checks that need it, such as validating the
+    /// FTS5 indexes in `integrity_problems`. Still neither migrates nor creates anything.
+    pub fn open_unmigrated(data_dir: Option<&Path>) -> Result<Self> {
+        Self::open_existing(data_dir, OpenFlags::SQLITE_OPEN_READ_WRITE)
+    }
 
-        let document_id: i64 = self.conn.query_row(
-            "SELECT id FROM documents WHERE repo=?1 AND kind='issue' AND external_id=?2",
-            params![repo, &external_id],
-            |row| row.get(0),
-        )?;
+    fn open_existing(data_dir: Option<&Path>, flags: OpenFlags) -> Result<Self> {
+        let path = database_path(data_dir)?;
+        let conn = Connection::open_with_flags(&path, flags)
+            .with_context(|| format!("failed to open database at {}", path.display()))?;
+        Ok(Self { conn, fts: false })
+    }
+
+    /// A private, empty cache that disappears when dropped.
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self> {
+        let mut conn = Connection::open_in_memory()?;
+        Self::apply_pragmas(&conn)?;
+        Self::migrate(&mut conn)?;
+        let fts = Self::create_fts(&conn)?;
+        Ok(Self { conn, fts })
+    }
+
+    pub fn upsert_issue(
+        &self,
+        repo: &str,
+        issue: &Issue,
+        max_body_size: Option<usize>,
+    ) -> Result<()> {
+        let mut body = issue.body.clone().unwrap_or_default();
+        let mut original_body_length = None;
+        if let Some(limit) = max_body_size
+            && body.len() > limit
+        {
+            original_body_length = Some(body.len() as i64);
+            let mut cut = limit;
+            while !body.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            body.truncate(cut);
+            body.push_str(TRUNCATION_MARKER);
+        }
 
         let state = match issue.state {
             IssueState::Open => "open",
             IssueState::Closed => "closed",
             _ => "unknown",
         };
-        let labels = if issue.labels.is_empty() {
-            String::new()
-        } else {
-            issue
-                .labels
-                .iter()
-                .map(|label| label.name.clone())
-                .collect::<Vec<_>>()
-                .join(", ")
-        };
+        let state_reason = issue.state_reason.as_ref().map(|reason| match reason {
+            IssueStateReason::Completed => "completed",
+            IssueStateReason::NotPlanned => "not_planned",
+            IssueStateReason::Reopened => "reopened",
+            _ => "unknown",
+        });
+        let labels = issue
+            .labels
+            .iter()
+            .map(|label| label.name.clone())
+            .collect::<Vec<_>>();
+        write_issue_row(
+            &self.conn,
+            self.fts,
+            repo,
+            &IssueRow {
+                number: issue.number as i64,
+                is_pull_request: issue.pull_request.is_some(),
+                title: &issue.title,
+                body: &body,
+                original_body_length,
+                updated_at: &issue.updated_at.to_rfc3339(),
+                state: Some(state),
+                state_reason,
+                labels: &labels,
+                assignees: &issue_assignees(issue),
+                author: Some(&issue.user.login),
+                created_at: Some(&issue.created_at.to_rfc3339()),
+                comment_count: i64::from(issue.comments),
+                milestone: issue
+                    .milestone
+                    .as_ref()
+                    .map(|milestone| milestone.title.as_str()),
+                triage_state: None,
+            },
+            &Utc::now().to_rfc3339(),
+        )?;
+        Ok(())
+    }
 
-        self.conn.execute(
-            "INSERT INTO issue_meta (document_id, number, state, labels)
-             VALUES (?1, ?2, ?3, ?4)
-             ON CONFLICT(document_id) DO UPDATE SET
-                 number=excluded.number,
-                 state=excluded.state,
-                 labels=excluded.labels",
-            params![document_id, issue.number as i64, state, labels],
+    /// Restores an archive from `notehub export --format json` in one transaction. Issues are
+    /// upserted like a sync would, unless the cached copy was updated more recently; notes are
+    /// added unless an identical one already exists.
+    pub fn import_archive(&mut self, archive: &Archive) -> Result<Vec<ImportSummary>> {
+        ensure!(
+            archive.schema_version <= SCHEMA_VERSION,
+            "archive uses schema version {} but this build only understands up to {SCHEMA_VERSION}; upgrade notehub",
+            archive.schema_version
+        );
+        let fts = self.fts;
+        let synced_at = Utc::now().to_rfc3339();
+        let tx = self.conn.transaction()?;
+        let mut summaries = Vec::new();
+        for archived in &archive.repos {
+            let mut summary = ImportSummary {
+                repo: archived.repo.clone(),
+                issues: 0,
+                stale_issues: 0,
+                notes: 0,
+                duplicate_notes: 0,
+            };
+            for issue in &archived.issues {
+                let repo = archived.repo.as_str();
+                let cached: Option<(i64, String)> = tx
+                    .query_row(
+                        &format!(
+                            "SELECT id, updated_at FROM documents
+                             WHERE repo = ?1 AND kind IN {ISSUE_KINDS_SQL} AND external_id = ?2"
+                        ),
+                        params![repo, issue.number.to_string()],
+                        |row| Ok((row.get(0)?, row.get(1)?)),
+                    )
+                    .optional()?;
+                let document_id = match cached {
+                    // A copy synced after the export was taken is newer; keep it, add only notes.
+                    Some((id, cached_at))
+                        if parse_timestamp(&cached_at) > parse_timestamp(&issue.updated_at) =>
+                    {
+                        summary.stale_issues += 1;
+                        id
+                    }
+                    _ => {
+                        summary.issues += 1;
+                        write_issue_row(
+                            &tx,
+                            fts,
+                            repo,
+                            &IssueRow {
+                                number: issue.number,
+                                is_pull_request: issue.is_pull_request == Some(true),
+                                title: &issue.title,
+                                body: issue.body.as_deref().unwrap_or_default(),
+                                original_body_length: None,
+                                updated_at: &issue.updated_at,
+                                state: issue.state.as_deref(),
+                                state_reason: issue.state_reason.as_deref(),
+                                labels: &issue.labels,
+                                assignees: &issue.assignees,
+                                author: issue.author.as_deref(),
+                                created_at: issue.created_at.as_deref(),
+                                comment_count: issue.comment_count,
+                                milestone: issue.milestone.as_deref(),
+                                triage_state: issue.triage_state.as_deref(),
+                            },
+                            &synced_at,
+                        )?
+                    }
+                };
+
+                for note in &issue.notes {
+                    let added = tx.execute(
+                        "INSERT INTO notes (document_id, anchor, body, created_at, updated_at)
+                         SELECT ?1, ?2, ?3, ?4, ?5
+                         WHERE NOT EXISTS (
+                             SELECT 1 FROM notes
+                             WHERE document_id = ?1 AND body = ?3 AND created_at = ?4
+                         )",
+                        params![
+                            document_id,
+                            &note.anchor,
+                            &note.body,
+                            &note.created_at,
+                            &note.updated_at
+                        ],
+                    )?;
+                    if added > 0 {
+                        summary.notes += 1;
+                    } else {
+                        summary.duplicate_notes += 1;
+                    }
+                }
+            }
+            summaries.push(summary);
+        }
+        tx.commit()?;
+        Ok(summaries)
+    }
+
+    /// Moves everything cached under `old` to `new`: issues, drafts and their notes, sync
+    /// cursors and history, and note cross-references. Returns the number of documents moved.
+    pub fn rename_repo(&self, old: &str, new: &str) -> Result<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        let taken: bool = tx.query_row(
+            "SELECT EXISTS (SELECT 1 FROM documents WHERE repo = ?1)",
+            [new],
+            |row| row.get(0),
+        )?;
+        ensure!(!taken, "the cache already holds issues for {new}");
+        let moved = tx.execute(
+            "UPDATE documents SET repo = ?2 WHERE repo = ?1",
+            params![old, new],
         )?;
+        tx.execute("DELETE FROM sync_state WHERE repo = ?1", [new])?;
+        tx.execute(
+            "UPDATE sync_state SET repo = ?2 WHERE repo = ?1",
+            params![old, new],
+        )?;
+        // Rows left under the old name duplicate one that already exists under the new name.
+        tx.execute(
+            "UPDATE OR IGNORE sync_runs SET repo = ?2 WHERE repo = ?1",
+            params![old, new],
+        )?;
+        tx.execute("DELETE FROM sync_runs WHERE repo = ?1", [old])?;
+        tx.execute(
+            "UPDATE OR IGNORE note_refs SET repo = ?2 WHERE repo = ?1",
+            params![old, new],
+        )?;
+        tx.execute("DELETE FROM note_refs WHERE repo = ?1", [old])?;
+        tx.commit()?;
+        Ok(moved)
+    }
 
-        Ok(())
+    /// Deletes every cached issue and pull request of `repo`, with their metadata, comments
+    /// and timelines, and resets its sync cursors so the next sync refetches everything.
+    /// Drafts are kept. Refuses when the issues carry notes unless `include_notes` is set.
+    pub fn clear_repo(&self, repo: &str, include_notes: bool) -> Result<ClearOutcome> {
+        let tx = self.conn.unchecked_transaction()?;
+        let notes: i64 = tx.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM notes
+             JOIN documents ON documents.id = notes.document_id
+             WHERE documents.repo = ?1 AND documents.kind IN {ISSUE_KINDS_SQL}"
+            ),
+            [repo],
+            |row| row.get(0),
+        )?;
+        ensure!(
+            notes == 0 || include_notes,
+            "{repo} has {notes} note(s) on cached issues; pass --notes to delete them too"
+        );
+        if self.fts {
+            tx.execute(
+                &format!(
+                    "DELETE FROM documents_fts WHERE rowid IN
+                     (SELECT id FROM documents
+                      WHERE repo = ?1 AND kind IN {ISSUE_KINDS_SQL})"
+                ),
+                [repo],
+            )?;
+        }
+        let documents = tx.execute(
+            &format!("DELETE FROM documents WHERE repo = ?1 AND kind IN {ISSUE_KINDS_SQL}"),
+            [repo],
+        )?;
+        tx.execute("DELETE FROM sync_state WHERE repo = ?1", [repo])?;
+        tx.commit()?;
+        Ok(ClearOutcome {
+            documents,
+            notes: notes as usize,
+        })
     }
 
-    pub fn list_issues(&self, repo: &str) -> Result<Vec<StoredIssueSummary>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT issue_meta.number, documents.title
-             FROM documents
-             JOIN issue_meta ON issue_meta.document_id = documents.id
-             WHERE documents.repo = ?1 AND documents.kind = 'issue'
-             ORDER BY issue_meta.number DESC",
+    /// Number of issue documents cached for `repo`.
+    pub fn cached_issue_count(&self, repo: &str) -> Result<usize> {
+        let count: i64 = self.conn.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM documents WHERE repo = ?1 AND kind IN {ISSUE_KINDS_SQL}"
+            ),
+            [repo],
+            |row| row.get(0),
         )?;
+        Ok(count as usize)
+    }
 
-        let rows = stmt.query_map([repo], |row| {
-            Ok(StoredIssueSummary {
+    /// Deletes cached issues of `repo` whose numbers are not in `keep`, along with their notes.
+    /// Unless `force_with_notes` is set, issues that still have notes are left in place.
+    pub fn prune_issues(
+        &mut self,
+        repo: &str,
+        keep: &[u64],
+        force_with_notes: bool,
+    ) -> Result<PruneOutcome> {
+        let keep: std::collections::HashSet<u64> = keep.iter().copied().collect();
+        let fts = self.fts;
+        let tx = self.conn.transaction()?;
+        let stale = {
+            let mut stmt = tx.prepare(&format!(
+                "SELECT documents.id, issue_meta.number,
+                        (SELECT COUNT(*) FROM notes WHERE notes.document_id = documents.id)
+                 FROM documents
+                 JOIN issue_meta ON issue_meta.document_id = documents.id
+                 WHERE documents.repo = ?1 AND documents.kind IN {ISSUE_KINDS_SQL}
+                 ORDER BY issue_meta.number"
+            ))?;
+            let rows = stmt.query_map([repo], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)? as u64,
+                    row.get::<_, i64>(2)? as usize,
+                ))
+            })?;
+            let mut stale = Vec::new();
+            for row in rows {
+                let row = row?;
+                if !keep.contains(&row.1) {
+                    stale.push(row);
+                }
+            }
+            stale
+        };
+
+        let mut outcome = PruneOutcome::default();
+        for (document_id, number, notes) in stale {
+            if notes > 0 && !force_with_notes {
+                outcome.spared.push((number, notes));
+                continue;
+            }
+            if fts {
+                tx.execute("DELETE FROM documents_fts WHERE rowid = ?1", [document_id])?;
+            }
+            tx.execute("DELETE FROM documents WHERE id = ?1", [document_id])?;
+            outcome.removed.push((number, notes));
+        }
+        tx.commit()?;
+        Ok(outcome)
+    }
+
+    /// Returns the subset of `numbers` lacking a cached document or its `issue_meta` row.
+    pub fn issues_missing_meta(&self, repo: &str, numbers: &[u64]) -> Result<Vec<u64>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT issue_meta.document_id
+             FROM documents
+             LEFT JOIN issue_meta ON issue_meta.document_id = documents.id
+             WHERE documents.repo = ?1 AND documents.kind IN {ISSUE_KINDS_SQL} AND documents.external_id = ?2"
+        ))?;
+        let mut missing = Vec::new();
+        for &number in numbers {
+            let meta: Option<Option<i64>> = stmt
+                .query_row(params![repo, number.to_string()], |row| row.get(0))
+                .optional()?;
+            if !matches!(meta, Some(Some(_))) {
+                missing.push(number);
+            }
+        }
+        Ok(missing)
+    }
+
+    pub fn list_issues(&self, repo: &str, filter: &IssueFilter) -> Result<Vec<StoredIssueSummary>> {
+        let mut issues = Vec::new();
+        self.for_each_issue(repo, filter, |issue| {
+            issues.push(issue);
+            Ok(())
+        })?;
+        Ok(issues)
+    }
+
+    /// Streams matching issues to `visit` one row at a time instead of collecting them.
+    pub fn for_each_issue<F>(&self, repo: &str, filter: &IssueFilter, mut visit: F) -> Result<()>
+    where
+        F: FnMut(StoredIssueSummary) -> Result<()>,
+    {
+        let (conditions, mut values) = issue_filter_sql(repo, filter);
+        let mut sql = format!(
+            "SELECT issue_meta.number, documents.title, issue_meta.state, issue_meta.state_reason,
+                    {ASSIGNEE_COUNT_SQL}, issue_meta.labels, issue_meta.assignees,
+                    issue_meta.author_login, documents.updated_at, issue_meta.comment_count
+             {conditions}"
+        );
+        let (newest_first, a_to_z) = if filter.reverse {
+            ("ASC", "DESC")
+        } else {
+            ("DESC", "ASC")
+        };
+        sql.push_str(&match filter.order {
+            IssueOrder::Number => format!(" ORDER BY issue_meta.number {newest_first}"),
+            IssueOrder::Updated => format!(
+                " ORDER BY julianday(documents.updated_at) {newest_first}, issue_meta.number DESC"
+            ),
+            IssueOrder::Created => format!(
+                " ORDER BY issue_meta.created_at IS NULL,
+                           julianday(issue_meta.created_at) {newest_first},
+                           issue_meta.number DESC"
+            ),
+            IssueOrder::Title => {
+                format!(" ORDER BY documents.title COLLATE NOCASE {a_to_z}, issue_meta.number DESC")
+            }
+        });
+        if filter.limit.is_some() || filter.offset > 0 {
+            // SQLite treats a negative LIMIT as "no limit", which lets OFFSET stand alone.
+            values.push(Value::from(filter.limit.map_or(-1, i64::from)));
+            values.push(Value::from(filter.offset));
+            sql.push_str(&format!(
+                " LIMIT ?{} OFFSET ?{}",
+                values.len() - 1,
+                values.len()
+            ));
+        }
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let mut rows = stmt.query(params_from_iter(values))?;
+        while let Some(row) = rows.next()? {
+            visit(StoredIssueSummary {
                 number: row.get(0)?,
                 title: row.get(1)?,
-            })
-        })?;
+                state: row.get(2)?,
+                state_reason: row.get(3)?,
+                assignee_count: row.get(4)?,
+                labels: split_list(row.get(5)?),
+                assignees: split_list(row.get(6)?),
+                author_login: row.get(7)?,
+                updated_at: row.get(8)?,
+                comment_count: row.get(9)?,
+            })?;
+        }
+        Ok(())
+    }
 
-        let mut issues = Vec::new();
+    /// Number of issues matching `filter`, ignoring its limit and offset.
+    pub fn count_issues(&self, repo: &str, filter: &IssueFilter) -> Result<usize> {
+        let (conditions, values) = issue_filter_sql(repo, filter);
+        let count: i64 = self.conn.query_row(
+            &format!("SELECT COUNT(*) {conditions}"),
+            params_from_iter(values),
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// Creation times of the open issues cached for `repo`; `None` for rows cached before
+    /// creation dates were stored.
+    pub fn open_issue_created_at(&self, repo: &str) -> Result<Vec<Option<DateTime<Utc>>>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT issue_meta.created_at
+             FROM documents
+             JOIN issue_meta ON issue_meta.document_id = documents.id
+             WHERE documents.repo = ?1 AND documents.kind IN {ISSUE_KINDS_SQL} AND issue_meta.state = 'open'"
+        ))?;
+        let rows = stmt.query_map([repo], |row| row.get::<_, Option<String>>(0))?;
+
+        let mut created = Vec::new();
         for row in rows {
-            issues.push(row?);
+            created.push(row?.as_deref().map(parse_timestamp));
         }
-        Ok(issues)
+        Ok(created)
     }
 
     pub fn get_issue(&self, repo: &str, number: u64) -> Result<Option<StoredIssueDetail>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT documents.title, documents.body, documents.updated_at
+        self.get_document(repo, DocumentKinds::Issues, number as i64)
+    }
+
+    /// Sets the issue's `synced_at` to now without refetching it. Returns the previous and new
+    /// timestamps, or `None` if the issue is not cached.
+    pub fn touch_issue(
+        &self,
+        repo: &str,
+        number: u64,
+    ) -> Result<Option<(DateTime<Utc>, DateTime<Utc>)>> {
+        let Some(document_id) = self.document_id(repo, number)? else {
+            return Ok(None);
+        };
+        let previous: String = self.conn.query_row(
+            "SELECT synced_at FROM documents WHERE id = ?1",
+            [document_id],
+            |row| row.get(0),
+        )?;
+        let now = Utc::now();
+        self.conn.execute(
+            "UPDATE documents SET synced_at = ?2 WHERE id = ?1",
+            params![document_id, now.to_rfc3339()],
+        )?;
+        Ok(Some((parse_timestamp(&previous), now)))
+    }
+
+    /// Sets the local triage state of a cached issue and, when a reason is given, records it
+    /// as a note in the same transaction. Returns false if the issue is not cached.
+    pub fn set_triage(
+        &mut self,
+        repo: &str,
+        number: u64,
+        state: &str,
+        reason: Option<&str>,
+    ) -> Result<bool> {
+        let Some(document_id) = self.document_id(repo, number)? else {
+            return Ok(false);
+        };
+        let tx = self.conn.transaction()?;
+        let note_id = match reason {
+            Some(reason) => {
+                let now = Utc::now().to_rfc3339();
+                tx.execute(
+                    "INSERT INTO notes (document_id, body, created_at, updated_at)
+                     VALUES (?1, ?2, ?3, ?3)",
+                    params![document_id, reason, now],
+                )?;
+                Some(tx.last_insert_rowid())
+            }
+            None => None,
+        };
+        tx.execute(
+            "UPDATE issue_meta SET triage_state = ?2, triage_note_id = ?3 WHERE document_id = ?1",
+            params![document_id, state, note_id],
+        )?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Applies `(repo, number, state)` triage states in one transaction, clearing any recorded
+    /// reason. Returns, per entry, whether the issue was cached and updated.
+    pub fn set_triage_batch(&mut self, entries: &[(String, u64, String)]) -> Result<Vec<bool>> {
+        let tx = self.conn.transaction()?;
+        let mut applied = Vec::with_capacity(entries.len());
+        {
+            let mut stmt = tx.prepare(&format!(
+                "UPDATE issue_meta SET triage_state = ?3, triage_note_id = NULL
+                 WHERE number = ?2
+                   AND document_id IN (SELECT id FROM documents WHERE repo = ?1 AND kind IN {ISSUE_KINDS_SQL})"
+            ))?;
+            for (repo, number, state) in entries {
+                applied.push(stmt.execute(params![repo, *number as i64, state])? > 0);
+            }
+        }
+        tx.commit()?;
+        Ok(applied)
+    }
+
+    /// Looks up a local draft by its (negative) number.
+    pub fn get_draft(&self, repo: &str, number: i64) -> Result<Option<StoredIssueDetail>> {
+        self.get_document(repo, DocumentKinds::Drafts, number)
+    }
+
+    /// Looks up a document of one of `kinds` by number.
+    fn get_document(
+        &self,
+        repo: &str,
+        kinds: DocumentKinds,
+        number: i64,
+    ) -> Result<Option<StoredIssueDetail>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT documents.title, documents.body, documents.updated_at,
+                    documents.original_body_length, issue_meta.is_pull_request,
+                    issue_meta.triage_state, notes.body, issue_meta.state, issue_meta.labels,
+                    issue_meta.author_login, issue_meta.created_at, issue_meta.assignees,
+                    issue_meta.milestone
              FROM documents
              JOIN issue_meta ON issue_meta.document_id = documents.id
-             WHERE documents.repo = ?1 AND documents.kind = 'issue' AND issue_meta.number = ?2",
-        )?;
+             LEFT JOIN notes ON notes.id = issue_meta.triage_note_id
+             WHERE documents.repo = ?1 AND documents.kind IN {} AND issue_meta.number = ?2",
+            kinds.sql()
+        ))?;
 
-        let mut rows = stmt.query(params![repo, number as i64])?;
+        let mut rows = stmt.query(params![repo, number])?;
         if let Some(row) = rows.next()? {
             let updated_at_str: String = row.get(2)?;
-            let updated_at = DateTime::parse_from_rfc3339(&updated_at_str)
-                .map(|dt| dt.with_timezone(&Utc))
-                .unwrap_or_else(|_| Utc::now());
+            let updated_at = parse_timestamp(&updated_at_str);
             Ok(Some(StoredIssueDetail {
-                number: number as i64,
+                number,
                 title: row.get(0)?,
                 body: row.get(1)?,
                 updated_at,
+                state: row.get(7)?,
+                labels: split_list(row.get(8)?),
+                assignees: split_list(row.get(11)?),
+                author: row.get(9)?,
+                created_at: row
+                    .get::<_, Option<String>>(10)?
+                    .map(|created_at| parse_timestamp(&created_at)),
+                original_body_length: row.get(3)?,
+                is_pull_request: row.get(4)?,
+                triage_state: row.get(5)?,
+                triage_reason: row.get(6)?,
+                milestone: row.get(12)?,
             }))
         } else {
             Ok(None)
         }
     }
 
-    fn apply_pragmas(conn: &Connection) -> Result<()> {
-        conn.pragma_update(None, "journal_mode", &"WAL")?;
-        conn.pragma_update(None, "foreign_keys", &"ON")?;
-        Ok(())
+    /// Every stored version of an issue's title and body, oldest first and ending with the
+    /// cached one. Empty if the issue is not cached.
+    pub fn issue_versions(&self, repo: &str, number: u64) -> Result<Vec<IssueVersion>> {
+        let Some(document_id) = self.document_id(repo, number)? else {
+            return Ok(Vec::new());
+        };
+        let mut stmt = self.conn.prepare(
+            "SELECT title, body, updated_at FROM (
+                 SELECT title, body, updated_at, id AS seq
+                 FROM issue_revisions WHERE document_id = ?1
+                 UNION ALL
+                 SELECT title, body, updated_at, NULL FROM documents WHERE id = ?1
+             )
+             ORDER BY seq IS NULL, seq",
+        )?;
+        let rows = stmt.query_map([document_id], |row| {
+            let updated_at: String = row.get(2)?;
+            Ok(IssueVersion {
+                title: row.get(0)?,
+                body: row.get(1)?,
+                updated_at: parse_timestamp(&updated_at),
+            })
+        })?;
+
+        let mut versions = Vec::new();
+        for row in rows {
+            versions.push(row?);
+        }
+        Ok(versions)
     }
 
-    fn migrate(conn: &Connection) -> Result<()> {
-        conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS documents (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                repo TEXT NOT NULL,
-                kind TEXT NOT NULL,
-                external_id TEXT NOT NULL,
-                title TEXT NOT NULL,
-                body TEXT,
-                updated_at TEXT NOT NULL,
-                synced_at TEXT NOT NULL,
-                UNIQUE(repo, kind, external_id)
-            );
+    /// Full-text search over cached issue titles and bodies, best matches first.
+    pub fn search_issues(
+        &self,
+        repos: &[String],
+        query: &str,
+        context_tokens: u8,
+        limit: usize,
+    ) -> Result<Vec<IssueSearchHit>> {
+        let mut values: Vec<Value> = vec![
+            Value::from(query.to_string()),
+            Value::from(i64::from(context_tokens)),
+        ];
+        let mut placeholders = Vec::new();
+        for repo in repos {
+            values.push(Value::from(repo.clone()));
+            placeholders.push(format!("?{}", values.len()));
+        }
+        values.push(Value::from(limit as i64));
+        let sql = if self.fts {
+            format!(
+                "SELECT documents.repo, issue_meta.number, documents.title,
+                        snippet(documents_fts, -1, '[', ']', '...', ?2)
+                 FROM documents_fts
+                 JOIN documents ON documents.id = documents_fts.rowid
+                 JOIN issue_meta ON issue_meta.document_id = documents.id
+                 WHERE documents_fts MATCH ?1
+                   AND documents.kind IN {ISSUE_KINDS_SQL}
+                   AND documents.repo IN ({})
+                 ORDER BY bm25(documents_fts)
+                 LIMIT ?{}",
+                placeholders.join(", "),
+                values.len()
+            )
+        } else {
+            // Without FTS5, match the whole query as a substring and cut a snippet around it.
+            format!(
+                "SELECT documents.repo, issue_meta.number, documents.title,
+                        substr(COALESCE(documents.body, ''),
+                               max(1, instr(lower(COALESCE(documents.body, '')), lower(?1)) - ?2 * 4),
+                               ?2 * 8)
+                 FROM documents
+                 JOIN issue_meta ON issue_meta.document_id = documents.id
+                 WHERE (documents.title LIKE '%' || ?1 || '%'
+                        OR documents.body LIKE '%' || ?1 || '%')
+                   AND documents.kind IN {ISSUE_KINDS_SQL}
+                   AND documents.repo IN ({})
+                 ORDER BY documents.title LIKE '%' || ?1 || '%' DESC, issue_meta.number DESC
+                 LIMIT ?{}",
+                placeholders.join(", "),
+                values.len()
+            )
+        };
 
-            CREATE TABLE IF NOT EXISTS issue_meta (
-                document_id INTEGER PRIMARY KEY,
-                number INTEGER NOT NULL,
-                state TEXT,
-                labels TEXT,
-                FOREIGN KEY(document_id) REFERENCES documents(id) ON DELETE CASCADE
-            );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(values), |row| {
+            Ok(IssueSearchHit {
+                repo: row.get(0)?,
+                number: row.get(1)?,
+                title: row.get(2)?,
+                snippet: row.get(3)?,
+            })
+        })?;
 
-            CREATE TABLE IF NOT EXISTS notes (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                document_id INTEGER NOT NULL,
-                anchor TEXT,
-                body TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL,
-                FOREIGN KEY(document_id) REFERENCES documents(id) ON DELETE CASCADE
-            );
+        let mut hits = Vec::new();
+        for row in rows {
+            hits.push(row.with_context(|| format!("invalid search query: {query}"))?);
+        }
+        Ok(hits)
+    }
 
-            CREATE TABLE IF NOT EXISTS sync_state (
-                repo TEXT NOT NULL,
-                resource TEXT NOT NULL,
-                cursor TEXT,
-                updated_at TEXT NOT NULL,
-                PRIMARY KEY (repo, resource)
-            );",
+    /// Stores an offline-authored issue as a `draft` document and returns its number, which is
+    /// negative so it can never collide with a GitHub issue.
+    pub fn insert_draft(&mut self, repo: &str, title: &str, body: &str) -> Result<i64> {
+        let fts = self.fts;
+        let tx = self.conn.transaction()?;
+        let lowest: i64 = tx.query_row(
+            "SELECT COALESCE(MIN(issue_meta.number), 0)
+             FROM documents
+             JOIN issue_meta ON issue_meta.document_id = documents.id
+             WHERE documents.repo = ?1 AND documents.kind = 'draft'",
+            [repo],
+            |row| row.get(0),
         )?;
-        Ok(())
+        let number = lowest.min(0) - 1;
+        let now = Utc::now().to_rfc3339();
+        tx.execute(
+            "INSERT INTO documents (repo, kind, external_id, title, body, updated_at, synced_at)
+             VALUES (?1, 'draft', ?2, ?3, ?4, ?5, ?5)",
+            params![repo, number.to_string(), title, body, &now],
+        )?;
+        let document_id = tx.last_insert_rowid();
+        if fts {
+            tx.execute(
+                "INSERT INTO documents_fts (rowid, title, body) VALUES (?1, ?2, ?3)",
+                params![document_id, title, body],
+            )?;
+        }
+        tx.execute(
+            "INSERT INTO issue_meta (document_id, number, state) VALUES (?1, ?2, 'draft')",
+            params![document_id, number],
+        )?;
+        tx.commit()?;
+        Ok(number)
     }
-}
 
-fn database_path() -> Result<PathBuf> {
-    let dirs = directories::ProjectDirs::from("com", "LexicalMathical", "NoteHub")
-        .context("unable to determine data directory")?;
-    Ok(dirs.data_dir().join(DB_FILE_NAME))
+    /// Re-keys a draft to the GitHub issue created from it, keeping the document (and so its
+    /// notes) and refreshing it from `issue`.
+    pub fn promote_draft(
+        &self,
+        repo: &str,
+        number: i64,
+        issue: &Issue,
+        max_body_size: Option<usize>,
+    ) -> Result<()> {
+        let updated = self.conn.execute(
+            "UPDATE documents SET kind = 'issue', external_id = ?3
+             WHERE repo = ?1 AND kind = 'draft'
+               AND id IN (SELECT document_id FROM issue_meta WHERE number = ?2)",
+            params![repo, number, issue.number.to_string()],
+        )?;
+        ensure!(updated == 1, "draft {number} not found in {repo}");
+        self.upsert_issue(repo, issue, max_body_size)
+    }
+
+    /// Returns issue, state and note counts for every cached repository in a single query.
+    pub fn repo_stats(&self) -> Result<Vec<RepoStats>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT documents.repo,
+                    COUNT(*),
+                    COALESCE(SUM(issue_meta.state = 'open'), 0),
+                    COALESCE(SUM(issue_meta.state = 'closed'), 0),
+                    COALESCE(note_counts.notes, 0),
+                    MIN(documents.synced_at),
+                    MAX(documents.synced_at)
+             FROM documents
+             JOIN issue_meta ON issue_meta.document_id = documents.id
+             LEFT JOIN (
+                 SELECT documents.repo AS repo, COUNT(*) AS notes
+                 FROM notes
+                 JOIN documents ON documents.id = notes.document_id
+                 GROUP BY documents.repo
+             ) AS note_counts ON note_counts.repo = documents.repo
+             WHERE documents.kind IN {ISSUE_KINDS_SQL}
+             GROUP BY documents.repo
+             ORDER BY documents.repo"
+        ))?;
+        let rows = stmt.query_map([], |row| {
+            let first_synced: Option<String> = row.get(5)?;
+            let last_synced: Option<String> = row.get(6)?;
+            Ok(RepoStats {
+                repo: row.get(0)?,
+                issues: row.get(1)?,
+                open: row.get(2)?,
+                closed: row.get(3)?,
+                notes: row.get(4)?,
+                first_synced: first_synced.as_deref().map(parse_timestamp),
+                last_synced: last_synced.as_deref().map(parse_timestamp),
+            })
+        })?;
+
+        let mut stats = Vec::new();
+        for row in rows {
+            stats.push(row?);
+        }
+        Ok(stats)
+    }
+
+    /// Streams every cached issue of `repo` with its notes to `visit`, one at a time and in
+    /// number order, so large caches are never held in memory whole.
+    pub fn for_each_archived_issue<F>(&self, repo: &str, mut visit: F) -> Result<()>
+    where
+        F: FnMut(ArchivedIssue) -> Result<()>,
+    {
+        let mut notes_stmt = self.conn.prepare(&format!(
+            "SELECT notes.id, notes.anchor, notes.body, notes.created_at, notes.updated_at
+             FROM notes
+             WHERE notes.document_id = ?1
+             ORDER BY {NOTE_ORDER_SQL}"
+        ))?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT documents.id, issue_meta.number, documents.title, documents.body,
+                    issue_meta.state, issue_meta.state_reason, issue_meta.labels,
+                    issue_meta.assignees, issue_meta.author_login, issue_meta.created_at,
+                    documents.updated_at, issue_meta.is_pull_request, issue_meta.triage_state,
+                    issue_meta.comment_count, issue_meta.milestone
+             FROM documents
+             JOIN issue_meta ON issue_meta.document_id = documents.id
+             WHERE documents.repo = ?1 AND documents.kind IN {ISSUE_KINDS_SQL}
+             ORDER BY issue_meta.number"
+        ))?;
+        let mut rows = stmt.query([repo])?;
+        while let Some(row) = rows.next()? {
+            let document_id: i64 = row.get(0)?;
+            let notes = notes_stmt
+                .query_map([document_id], |note| {
+                    Ok(ArchivedNote {
+                        id: note.get(0)?,
+                        anchor: note.get(1)?,
+                        body: note.get(2)?,
+                        created_at: note.get(3)?,
+                        updated_at: note.get(4)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            visit(ArchivedIssue {
+                number: row.get(1)?,
+                title: row.get(2)?,
+                body: row.get(3)?,
+                state: row.get(4)?,
+                state_reason: row.get(5)?,
+                labels: split_list(row.get(6)?),
+                assignees: split_list(row.get(7)?),
+                author: row.get(8)?,
+                created_at: row.get(9)?,
+                updated_at: row.get(10)?,
+                is_pull_request: row.get(11)?,
+                triage_state: row.get(12)?,
+                comment_count: row.get(13)?,
+                milestone: row.get(14)?,
+                notes,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Full-text search over note bodies, best matches first, optionally limited to one
+    /// repository.
+    pub fn search_notes(&self, query: &str, repo: Option<&str>) -> Result<Vec<NoteHit>> {
+        let sql = if self.fts {
+            "SELECT documents.repo, issue_meta.number, notes.id,
+                    snippet(notes_fts, 0, '[', ']', '...', 12)
+             FROM notes_fts
+             JOIN notes ON notes.id = notes_fts.rowid
+             JOIN documents ON documents.id = notes.document_id
+             JOIN issue_meta ON issue_meta.document_id = documents.id
+             WHERE notes_fts MATCH ?1
+               AND (?2 IS NULL OR documents.repo = ?2)
+             ORDER BY bm25(notes_fts)"
+        } else {
+            // Without FTS5, match the whole query as a substring and cut a snippet around it.
+            "SELECT documents.repo, issue_meta.number, notes.id,
+                    substr(notes.body, max(1, instr(lower(notes.body), lower(?1)) - 48), 96)
+             FROM notes
+             JOIN documents ON documents.id = notes.document_id
+             JOIN issue_meta ON issue_meta.document_id = documents.id
+             WHERE notes.body LIKE '%' || ?1 || '%'
+               AND (?2 IS NULL OR documents.repo = ?2)
+             ORDER BY documents.repo, issue_meta.number, notes.id"
+        };
+
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map(params![query, repo], |row| {
+            Ok(NoteHit {
+                repo: row.get(0)?,
+                number: row.get(1)?,
+                note_id: row.get(2)?,
+                snippet: row.get(3)?,
+            })
+        })?;
+
+        let mut hits = Vec::new();
+        for row in rows {
+            hits.push(row.with_context(|| format!("invalid search query: {query}"))?);
+        }
+        Ok(hits)
+    }
+
+    /// Returns every note with the issue it belongs to, optionally limited to one repository.
+    pub fn notes_with_context(&self, repo: Option<&str>) -> Result<Vec<NoteWithContext>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT notes.id, documents.repo, issue_meta.number, notes.body
+             FROM notes
+             JOIN documents ON documents.id = notes.document_id
+             JOIN issue_meta ON issue_meta.document_id = documents.id
+             WHERE ?1 IS NULL OR documents.repo = ?1
+             ORDER BY documents.repo, issue_meta.number, {NOTE_ORDER_SQL}"
+        ))?;
+
+        let rows = stmt.query_map([repo], |row| {
+            Ok(NoteWithContext {
+                id: row.get(0)?,
+                repo: row.get(1)?,
+                number: row.get(2)?,
+                body: row.get(3)?,
+            })
+        })?;
+
+        let mut notes = Vec::new();
+        for row in rows {
+            notes.push(row?);
+        }
+        Ok(notes)
+    }
+
+    pub fn note_anchor(&self, id: i64) -> Result<Option<NoteAnchor>> {
+        let anchor = self
+            .conn
+            .query_row(
+                "SELECT documents.repo, issue_meta.number, notes.anchor
+                 FROM notes
+                 JOIN documents ON documents.id = notes.document_id
+                 JOIN issue_meta ON issue_meta.document_id = documents.id
+                 WHERE notes.id = ?1",
+                [id],
+                |row| {
+                    Ok(NoteAnchor {
+                        repo: row.get(0)?,
+                        number: row.get(1)?,
+                        anchor: row.get(2)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(anchor)
+    }
+
+    /// Returns notes grouped by issue, optionally limited to one repository and to notes created
+    /// or updated at or after `since`.
+    pub fn export_notes(
+        &self,
+        repo: Option<&str>,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ExportedNote>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT notes.id, documents.repo, issue_meta.number, documents.title, notes.body,
+                    notes.created_at, notes.updated_at
+             FROM notes
+             JOIN documents ON documents.id = notes.document_id
+             JOIN issue_meta ON issue_meta.document_id = documents.id
+             WHERE (?1 IS NULL OR documents.repo = ?1)
+               AND (?2 IS NULL
+                    OR julianday(notes.created_at) >= julianday(?2)
+                    OR julianday(notes.updated_at) >= julianday(?2))
+             ORDER BY documents.repo, issue_meta.number, {NOTE_ORDER_SQL}"
+        ))?;
+
+        let since = since.map(|since| since.to_rfc3339());
+        let rows = stmt.query_map(params![repo, since], |row| {
+            let created_at: String = row.get(5)?;
+            let updated_at: String = row.get(6)?;
+            Ok(ExportedNote {
+                id: row.get(0)?,
+                repo: row.get(1)?,
+                number: row.get(2)?,
+                issue_title: row.get(3)?,
+                body: row.get(4)?,
+                created_at: parse_timestamp(&created_at),
+                updated_at: parse_timestamp(&updated_at),
+            })
+        })?;
+
+        let mut notes = Vec::new();
+        for row in rows {
+            notes.push(row?);
+        }
+        Ok(notes)
+    }
+
+    /// Notes attached to one issue, in display order (chronological unless reordered).
+    pub fn list_notes(&self, repo: &str, number: u64) -> Result<Vec<StoredNote>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT notes.id, notes.body, notes.anchor, notes.created_at, notes.updated_at
+             FROM notes
+             JOIN documents ON documents.id = notes.document_id
+             JOIN issue_meta ON issue_meta.document_id = documents.id
+             WHERE documents.repo = ?1 AND documents.kind IN {ISSUE_KINDS_SQL} AND issue_meta.number = ?2
+             ORDER BY {NOTE_ORDER_SQL}"
+        ))?;
+
+        let rows = stmt.query_map(params![repo, number as i64], |row| {
+            let created_at: String = row.get(3)?;
+            let updated_at: String = row.get(4)?;
+            Ok(StoredNote {
+                id: row.get(0)?,
+                body: row.get(1)?,
+                anchor: row.get(2)?,
+                created_at: parse_timestamp(&created_at),
+                updated_at: parse_timestamp(&updated_at),
+            })
+        })?;
+
+        let mut notes = Vec::new();
+        for row in rows {
+            notes.push(row?);
+        }
+        Ok(notes)
+    }
+
+    /// Swaps a note with its neighbour in its issue's display order (`offset` -1 moves it up,
+    /// +1 down). Returns `None` if the note does not exist and `Some(false)` if it is already
+    /// first or last. The issue's notes get explicit positions the first time they are moved.
+    pub fn move_note(&mut self, id: i64, offset: i64) -> Result<Option<bool>> {
+        let tx = self.conn.transaction()?;
+        let document_id: Option<i64> = tx
+            .query_row("SELECT document_id FROM notes WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        let Some(document_id) = document_id else {
+            return Ok(None);
+        };
+        let order = {
+            let mut stmt = tx.prepare(&format!(
+                "SELECT notes.id FROM notes WHERE document_id = ?1 ORDER BY {NOTE_ORDER_SQL}"
+            ))?;
+            let rows = stmt.query_map([document_id], |row| row.get::<_, i64>(0))?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        let Some(current) = order.iter().position(|&note| note == id) else {
+            return Ok(None);
+        };
+        let Some(target) = current
+            .checked_add_signed(offset as isize)
+            .filter(|&target| target < order.len())
+        else {
+            return Ok(Some(false));
+        };
+
+        let mut order = order;
+        order.swap(current, target);
+        for (position, note) in order.iter().enumerate() {
+            tx.execute(
+                "UPDATE notes SET position = ?2 WHERE id = ?1",
+                params![note, position as i64 + 1],
+            )?;
+        }
+        tx.commit()?;
+        Ok(Some(true))
+    }
+
+    /// Reattaches a note to another cached document, bumping its `updated_at`. The note goes to
+    /// the end of its new issue's order and stops serving as the old issue's triage reason; a
+    /// `comment:` anchor is dropped, since the comment stays on the old issue. Returns `false`
+    /// if no note has that id.
+    pub fn reattach_note(&self, id: i64, new_document_id: i64) -> Result<bool> {
+        let tx = self.conn.unchecked_transaction()?;
+        let moved = tx.execute(
+            "UPDATE notes SET
+                 anchor = CASE
+                     WHEN document_id <> ?2 AND anchor LIKE 'comment:%' THEN NULL
+                     ELSE anchor
+                 END,
+                 document_id = ?2,
+                 position = NULL,
+                 updated_at = ?3
+             WHERE id = ?1",
+            params![id, new_document_id, Utc::now().to_rfc3339()],
+        )?;
+        tx.execute(
+            "UPDATE issue_meta SET triage_note_id = NULL
+             WHERE triage_note_id = ?1 AND document_id <> ?2",
+            params![id, new_document_id],
+        )?;
+        tx.commit()?;
+        Ok(moved > 0)
+    }
+
+    /// Attaches a new note, optionally anchored to a snippet or location, to a cached issue and
+    /// returns its id.
+    pub fn add_note(
+        &self,
+        repo: &str,
+        number: u64,
+        text: &str,
+        anchor: Option<&str>,
+    ) -> Result<i64> {
+        let document_id = self.document_id(repo, number)?.with_context(|| {
+            format!(
+                "issue #{number} is not cached for {repo}; run `notehub sync` or `notehub issue view {number}` first"
+            )
+        })?;
+        let now = Utc::now().to_rfc3339();
+        self.conn.execute(
+            "INSERT INTO notes (document_id, anchor, body, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?4)",
+            params![document_id, anchor, text, now],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Current text of a note, or `None` if no note has that id.
+    pub fn note_body(&self, id: i64) -> Result<Option<String>> {
+        let body = self
+            .conn
+            .query_row("SELECT body FROM notes WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        Ok(body)
+    }
+
+    /// Replaces a note's text, keeping the previous text in `note_revisions`. Returns `false`
+    /// if no note has that id.
+    pub fn update_note(&self, id: i64, text: &str) -> Result<bool> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO note_revisions (note_id, body, written_at)
+             SELECT id, body, updated_at FROM notes WHERE id = ?1",
+            [id],
+        )?;
+        let updated = tx.execute(
+            "UPDATE notes SET body = ?2, updated_at = ?3 WHERE id = ?1",
+            params![id, text, Utc::now().to_rfc3339()],
+        )?;
+        tx.commit()?;
+        Ok(updated > 0)
+    }
+
+    /// Deletes a note; returns `false` if no note has that id.
+    pub fn delete_note(&self, id: i64) -> Result<bool> {
+        let exists: bool = self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM notes WHERE id = ?1)",
+            [id],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Ok(false);
+        }
+        let removed = self.conn.execute("DELETE FROM notes WHERE id = ?1", [id])?;
+        Ok(removed > 0)
+    }
+
+    /// Records that note `note_id` relates to `repo#number`; returns false if already linked.
+    pub fn add_note_ref(&self, note_id: i64, repo: &str, number: i64) -> Result<bool> {
+        let inserted = self.conn.execute(
+            "INSERT OR IGNORE INTO note_refs (note_id, repo, number) VALUES (?1, ?2, ?3)",
+            params![note_id, repo, number],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    /// Issues a note cross-references, as `(repo, number)` pairs.
+    pub fn note_refs(&self, note_id: i64) -> Result<Vec<(String, i64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT repo, number FROM note_refs WHERE note_id = ?1 ORDER BY repo, number",
+        )?;
+        let rows = stmt.query_map([note_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        let mut refs = Vec::new();
+        for row in rows {
+            refs.push(row?);
+        }
+        Ok(refs)
+    }
+
+    /// Returns every recorded version of a note followed by its current text, or `None` if the
+    /// note does not exist.
+    pub fn note_versions(&self, id: i64) -> Result<Option<Vec<NoteVersion>>> {
+        let current = self
+            .conn
+            .query_row(
+                "SELECT body, updated_at FROM notes WHERE id = ?1",
+                [id],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+            )
+            .optional()?;
+        let Some((body, updated_at)) = current else {
+            return Ok(None);
+        };
+
+        let mut stmt = self.conn.prepare(
+            "SELECT body, written_at FROM note_revisions WHERE note_id = ?1 ORDER BY id ASC",
+        )?;
+        let rows = stmt.query_map([id], |row| {
+            let written_at: String = row.get(1)?;
+            Ok(NoteVersion {
+                body: row.get(0)?,
+                written_at: parse_timestamp(&written_at),
+            })
+        })?;
+
+        let mut versions = Vec::new();
+        for row in rows {
+            versions.push(row?);
+        }
+        versions.push(NoteVersion {
+            body,
+            written_at: parse_timestamp(&updated_at),
+        });
+        Ok(Some(versions))
+    }
+
+    /// The stored sync cursor for one resource of a repository, if any.
+    pub fn get_sync_cursor(&self, repo: &str, resource: &str) -> Result<Option<String>> {
+        let cursor = self
+            .conn
+            .query_row(
+                "SELECT cursor FROM sync_state WHERE repo = ?1 AND resource = ?2",
+                params![repo, resource],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
+        Ok(cursor)
+    }
+
+    pub fn set_sync_cursor(&self, repo: &str, resource: &str, cursor: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO sync_state (repo, resource, cursor, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(repo, resource) DO UPDATE SET
+                 cursor=excluded.cursor,
+                 updated_at=excluded.updated_at",
+            params![repo, resource, cursor, Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    /// Returns when each repository last finished a sync successfully.
+    pub fn last_successful_syncs(&self) -> Result<Vec<(String, DateTime<Utc>)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT repo, MAX(updated_at) FROM sync_runs WHERE status = 'succeeded' GROUP BY repo",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let at: String = row.get(1)?;
+            Ok((row.get(0)?, parse_timestamp(&at)))
+        })?;
+
+        let mut syncs = Vec::new();
+        for row in rows {
+            syncs.push(row?);
+        }
+        Ok(syncs)
+    }
+
+    /// Fills the `labels` table from the legacy `issue_meta.labels` string of every cached issue,
+    /// splitting on `", "`. Runs in one transaction; `progress` sees (done, total).
+    pub fn migrate_labels<F>(&mut self, mut progress: F) -> Result<LabelMigration>
+    where
+        F: FnMut(usize, usize),
+    {
+        let tx = self.conn.transaction()?;
+        let legacy = {
+            let mut stmt = tx.prepare(
+                "SELECT issue_meta.document_id, documents.repo, issue_meta.number, issue_meta.labels
+                 FROM issue_meta
+                 JOIN documents ON documents.id = issue_meta.document_id
+                 WHERE COALESCE(issue_meta.labels, '') <> ''
+                 ORDER BY documents.repo, issue_meta.number",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+
+        let mut migration = LabelMigration::default();
+        let total = legacy.len();
+        for (done, (document_id, repo, number, raw)) in legacy.into_iter().enumerate() {
+            let parts = raw.split(", ").collect::<Vec<_>>();
+            if parts
+                .iter()
+                .any(|part| part.trim().is_empty() || part.contains(',') || part.trim() != *part)
+            {
+                migration.unclear.push((repo, number, raw.clone()));
+            }
+            tx.execute("DELETE FROM labels WHERE document_id = ?1", [document_id])?;
+            for part in parts
+                .iter()
+                .map(|part| part.trim())
+                .filter(|part| !part.is_empty())
+            {
+                migration.labels += tx.execute(
+                    "INSERT OR IGNORE INTO labels (document_id, name) VALUES (?1, ?2)",
+                    params![document_id, part],
+                )?;
+            }
+            migration.issues += 1;
+            progress(done + 1, total);
+        }
+        tx.commit()?;
+        Ok(migration)
+    }
+
+    /// Registers a new sync run covering `repos`, all initially pending, and returns its id.
+    pub fn begin_sync_run(&mut self, repos: &[String]) -> Result<String> {
+        let now = Utc::now();
+        let run_id = now.format("%Y%m%dT%H%M%S%.3fZ").to_string();
+        let tx = self.conn.transaction()?;
+        for (position, repo) in repos.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO sync_runs (run_id, repo, position, status, updated_at)
+                 VALUES (?1, ?2, ?3, 'pending', ?4)",
+                params![run_id, repo, position as i64, now.to_rfc3339()],
+            )?;
+        }
+        tx.commit()?;
+        Ok(run_id)
+    }
+
+    pub fn mark_sync_run(&self, run_id: &str, repo: &str, status: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE sync_runs SET status = ?3, updated_at = ?4 WHERE run_id = ?1 AND repo = ?2",
+            params![run_id, repo, status, Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    /// Returns the most recent sync run if it still has pending or failed repositories.
+    pub fn latest_incomplete_sync_run(&self) -> Result<Option<IncompleteSyncRun>> {
+        let run_id: Option<String> =
+            self.conn
+                .query_row("SELECT MAX(run_id) FROM sync_runs", [], |row| row.get(0))?;
+        let Some(run_id) = run_id else {
+            return Ok(None);
+        };
+
+        let mut stmt = self.conn.prepare(
+            "SELECT repo, status FROM sync_runs WHERE run_id = ?1 ORDER BY position ASC",
+        )?;
+        let rows = stmt.query_map([&run_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+
+        let mut total = 0usize;
+        let mut remaining = Vec::new();
+        for row in rows {
+            let (repo, status) = row?;
+            total += 1;
+            if status == "pending" || status == "failed" {
+                remaining.push(repo);
+            }
+        }
+        if remaining.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(IncompleteSyncRun {
+            run_id,
+            total,
+            remaining,
+        }))
+    }
+
+    /// Returns true when the cached timeline was fetched for the issue's current revision.
+    pub fn timeline_is_current(
+        &self,
+        repo: &str,
+        number: u64,
+        updated_at: &DateTime<Utc>,
+    ) -> Result<bool> {
+        let fetched_for: Option<String> = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT issue_meta.timeline_updated_at
+                 FROM documents
+                 JOIN issue_meta ON issue_meta.document_id = documents.id
+                 WHERE documents.repo = ?1 AND documents.kind IN {ISSUE_KINDS_SQL} AND issue_meta.number = ?2"
+                ),
+                params![repo, number as i64],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
+        Ok(fetched_for.as_deref() == Some(updated_at.to_rfc3339().as_str()))
+    }
+
+    /// Inserts or refreshes one comment of a cached issue.
+    pub fn upsert_comment(&self, repo: &str, number: u64, comment: &Comment) -> Result<()> {
+        let document_id = self
+            .document_id(repo, number)?
+            .with_context(|| format!("issue #{number} is not cached for {repo}"))?;
+        self.conn.execute(
+            "INSERT INTO comments (comment_id, document_id, author, body, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(comment_id) DO UPDATE SET
+                 document_id=excluded.document_id,
+                 author=excluded.author,
+                 body=excluded.body,
+                 created_at=excluded.created_at",
+            params![
+                comment.id.0 as i64,
+                document_id,
+                &comment.user.login,
+                comment.body.as_deref().unwrap_or_default(),
+                comment.created_at.to_rfc3339()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Cached comments of an issue, oldest first.
+    pub fn list_comments(&self, repo: &str, number: u64) -> Result<Vec<StoredComment>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT comments.author, comments.body, comments.created_at
+             FROM comments
+             JOIN documents ON documents.id = comments.document_id
+             JOIN issue_meta ON issue_meta.document_id = documents.id
+             WHERE documents.repo = ?1 AND documents.kind IN {ISSUE_KINDS_SQL} AND issue_meta.number = ?2
+             ORDER BY comments.created_at ASC, comments.comment_id ASC"
+        ))?;
+        let rows = stmt.query_map(params![repo, number as i64], |row| {
+            let created_at: String = row.get(2)?;
+            Ok(StoredComment {
+                author: row.get(0)?,
+                body: row.get(1)?,
+                created_at: parse_timestamp(&created_at),
+            })
+        })?;
+
+        let mut comments = Vec::new();
+        for row in rows {
+            comments.push(row?);
+        }
+        Ok(comments)
+    }
+
+    /// Replaces the cached timeline of an issue and remembers which revision it reflects.
+    pub fn replace_events(
+        &mut self,
+        repo: &str,
+        number: u64,
+        updated_at: &DateTime<Utc>,
+        events: &[TimelineEvent],
+    ) -> Result<()> {
+        let document_id = self
+            .document_id(repo, number)?
+            .with_context(|| format!("issue #{number} is not cached for {repo}"))?;
+
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM events WHERE document_id = ?1", [document_id])?;
+        for event in events {
+            let Some(created_at) = event
+                .created_at
+                .or_else(|| event.author.as_ref().and_then(|author| author.date))
+            else {
+                continue;
+            };
+            let kind = serde_json::to_value(&event.event)
+                .ok()
+                .and_then(|value| value.as_str().map(str::to_string))
+                .unwrap_or_else(|| "unknown".to_string());
+            let actor = event
+                .actor
+                .as_ref()
+                .or(event.user.as_ref())
+                .map(|author| author.login.clone());
+            tx.execute(
+                "INSERT INTO events (document_id, type, actor, created_at, detail)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    document_id,
+                    kind,
+                    actor,
+                    created_at.to_rfc3339(),
+                    event_detail(event)
+                ],
+            )?;
+        }
+        tx.execute(
+            "UPDATE issue_meta SET timeline_updated_at = ?2 WHERE document_id = ?1",
+            params![document_id, updated_at.to_rfc3339()],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn list_events(&self, repo: &str, number: u64) -> Result<Vec<StoredEvent>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT events.type, events.actor, events.created_at, events.detail
+             FROM events
+             JOIN documents ON documents.id = events.document_id
+             JOIN issue_meta ON issue_meta.document_id = documents.id
+             WHERE documents.repo = ?1 AND documents.kind IN {ISSUE_KINDS_SQL} AND issue_meta.number = ?2
+             ORDER BY events.created_at ASC, events.id ASC"
+        ))?;
+
+        let rows = stmt.query_map(params![repo, number as i64], |row| {
+            let created_at: String = row.get(2)?;
+            Ok(StoredEvent {
+                kind: row.get(0)?,
+                actor: row.get(1)?,
+                created_at: parse_timestamp(&created_at),
+                detail: row.get(3)?,
+            })
+        })?;
+
+        let mut events = Vec::new();
+        for row in rows {
+            events.push(row?);
+        }
+        Ok(events)
+    }
+
+    /// The cached `updated_at` of an issue, or `None` if it is not cached.
+    pub fn peek_issue_updated_at(&self, repo: &str, number: u64) -> Result<Option<DateTime<Utc>>> {
+        let updated_at: Option<String> = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT documents.updated_at
+                 FROM documents
+                 JOIN issue_meta ON issue_meta.document_id = documents.id
+                 WHERE documents.repo = ?1 AND documents.kind IN {ISSUE_KINDS_SQL} AND issue_meta.number = ?2"
+                ),
+                params![repo, number as i64],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(updated_at.as_deref().map(parse_timestamp))
+    }
+
+    /// Row id of the cached issue or pull request `repo#number`.
+    pub fn document_id(&self, repo: &str, number: u64) -> Result<Option<i64>> {
+        let id = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT documents.id
+                 FROM documents
+                 JOIN issue_meta ON issue_meta.document_id = documents.id
+                 WHERE documents.repo = ?1 AND documents.kind IN {ISSUE_KINDS_SQL} AND issue_meta.number = ?2"
+                ),
+                params![repo, number as i64],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(id)
+    }
+
+    /// Writes the schema and rows as a plain SQL script loadable by any SQLite shell.
+    ///
+    /// With `repo`, only rows reachable from that repository are written and tables with no
+    /// link to a repository are left out. The full-text indexes are skipped; they are rebuilt on open.
+    pub fn export_sql(&self, out: &mut impl Write, repo: Option<&str>) -> Result<usize> {
+        let mut stmt = self.conn.prepare(
+            "SELECT name, sql FROM sqlite_master
+             WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name NOT LIKE 'documents_fts%'
+               AND name NOT LIKE 'notes_fts%'
+             ORDER BY rowid",
+        )?;
+        let tables = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        writeln!(out, "PRAGMA foreign_keys=OFF;")?;
+        writeln!(out, "BEGIN TRANSACTION;")?;
+        let mut rows_written = 0;
+        for (table, create) in tables {
+            let columns = table_columns(&self.conn, &table)?;
+            // A row owned by a note belongs to the note's repository, even when it also names
+            // another one (as `note_refs.repo` does).
+            let scope = match repo {
+                None => String::new(),
+                Some(_) if columns.iter().any(|c| c == "note_id") => " WHERE note_id IN (
+                     SELECT notes.id FROM notes
+                     JOIN documents ON documents.id = notes.document_id
+                     WHERE documents.repo = ?1)"
+                    .to_string(),
+                Some(_) if columns.iter().any(|c| c == "repo") => " WHERE repo = ?1".to_string(),
+                Some(_) if columns.iter().any(|c| c == "document_id") => {
+                    " WHERE document_id IN (SELECT id FROM documents WHERE repo = ?1)".to_string()
+                }
+                Some(_) => continue,
+            };
+
+            writeln!(out, "{create};")?;
+            let column_list = columns
+                .iter()
+                .map(|c| quote_identifier(c))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let mut select = self.conn.prepare(&format!(
+                "SELECT {column_list} FROM {}{scope} ORDER BY rowid",
+                quote_identifier(&table)
+            ))?;
+            let mut rows = match repo {
+                Some(repo) if !scope.is_empty() => select.query(params![repo])?,
+                _ => select.query([])?,
+            };
+            while let Some(row) = rows.next()? {
+                let mut literals = Vec::with_capacity(columns.len());
+                for index in 0..columns.len() {
+                    literals.push(sql_literal(row.get_ref(index)?));
+                }
+                writeln!(
+                    out,
+                    "INSERT INTO {} ({column_list}) VALUES ({});",
+                    quote_identifier(&table),
+                    literals.join(", ")
+                )?;
+                rows_written += 1;
+            }
+        }
+        writeln!(out, "COMMIT;")?;
+        Ok(rows_written)
+    }
+
+    /// Whether `sql` would only read from the database.
+    pub fn is_read_only(&self, sql: &str) -> Result<bool> {
+        Ok(self.conn.prepare(sql)?.readonly())
+    }
+
+    /// Problems reported by `PRAGMA integrity_check`; empty when the database is sound.
+    pub fn integrity_problems(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("PRAGMA integrity_check")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut problems = Vec::new();
+        for row in rows {
+            let line = row?;
+            if line != "ok" {
+                problems.push(line);
+            }
+        }
+        Ok(problems)
+    }
+
+    /// Schema version recorded in the database; 0 for one that predates versioning.
+    pub fn schema_version(&self) -> Result<usize> {
+        let versioned: bool = self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !versioned {
+            return Ok(0);
+        }
+        let version: Option<i64> =
+            self.conn
+                .query_row("SELECT MAX(version) FROM schema_version", [], |row| {
+                    row.get(0)
+                })?;
+        Ok(version.unwrap_or(0) as usize)
+    }
+
+    /// Runs one arbitrary SQL statement, rendering every value as text.
+    pub fn run_query(&self, sql: &str) -> Result<QueryOutput> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let columns = stmt
+            .column_names()
+            .into_iter()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if columns.is_empty() {
+            let changes = stmt.execute([])?;
+            return Ok(QueryOutput {
+                columns,
+                rows: Vec::new(),
+                changes,
+            });
+        }
+
+        let mut rows = Vec::new();
+        let mut result = stmt.query([])?;
+        while let Some(row) = result.next()? {
+            let mut values = Vec::with_capacity(columns.len());
+            for index in 0..columns.len() {
+                values.push(match row.get_ref(index)? {
+                    ValueRef::Null => "NULL".to_string(),
+                    ValueRef::Integer(i) => i.to_string(),
+                    ValueRef::Real(f) => f.to_string(),
+                    ValueRef::Text(text) => String::from_utf8_lossy(text).into_owned(),
+                    ValueRef::Blob(bytes) => format!("<{} bytes>", bytes.len()),
+                });
+            }
+            rows.push(values);
+        }
+        Ok(QueryOutput {
+            columns,
+            rows,
+            changes: 0,
+        })
+    }
+
+    fn apply_pragmas(conn: &Connection) -> Result<()> {
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        Ok(())
+    }
+
+    /// Brings the schema up to date, applying each pending step of `MIGRATIONS` in its own
+    /// transaction and recording the new version in `schema_version`.
+    fn migrate(conn: &mut Connection) -> Result<()> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL);",
+        )?;
+        let current: i64 = conn
+            .query_row("SELECT MAX(version) FROM schema_version", [], |row| {
+                row.get::<_, Option<i64>>(0)
+            })?
+            .unwrap_or(0);
+        ensure!(
+            current as usize <= MIGRATIONS.len(),
+            "database schema version {current} is newer than this build supports ({}); upgrade notehub",
+            MIGRATIONS.len()
+        );
+        for (index, step) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+            let version = index as i64 + 1;
+            let tx = conn.transaction()?;
+            step(&tx)
+                .with_context(|| format!("failed to migrate database to version {version}"))?;
+            tx.execute("DELETE FROM schema_version", [])?;
+            tx.execute(
+                "INSERT INTO schema_version (version) VALUES (?1)",
+                [version],
+            )?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Creates and backfills the full-text indexes. Returns `false` when this SQLite build lacks
+    /// FTS5, in which case searches use `LIKE` instead.
+    ///
+    /// The note index is kept current by triggers, so every path that writes notes (including
+    /// cascading deletes) updates it without further bookkeeping.
+    fn create_fts(conn: &Connection) -> Result<bool> {
+        if conn
+            .execute_batch(
+                "CREATE VIRTUAL TABLE IF NOT EXISTS documents_fts USING fts5(title, body);",
+            )
+            .is_err()
+        {
+            return Ok(false);
+        }
+        let indexed: i64 =
+            conn.query_row("SELECT COUNT(*) FROM documents_fts", [], |row| row.get(0))?;
+        if indexed == 0 {
+            conn.execute(
+                "INSERT INTO documents_fts (rowid, title, body)
+                 SELECT id, title, COALESCE(body, '') FROM documents",
+                [],
+            )?;
+        }
+
+        let has_note_index: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'notes_fts')",
+            [],
+            |row| row.get(0),
+        )?;
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS notes_fts
+                 USING fts5(body, content = 'notes', content_rowid = 'id');
+             CREATE TRIGGER IF NOT EXISTS notes_fts_insert AFTER INSERT ON notes BEGIN
+                 INSERT INTO notes_fts (rowid, body) VALUES (new.id, new.body);
+             END;
+             CREATE TRIGGER IF NOT EXISTS notes_fts_delete AFTER DELETE ON notes BEGIN
+                 INSERT INTO notes_fts (notes_fts, rowid, body) VALUES ('delete', old.id, old.body);
+             END;
+             CREATE TRIGGER IF NOT EXISTS notes_fts_update AFTER UPDATE OF body ON notes BEGIN
+                 INSERT INTO notes_fts (notes_fts, rowid, body) VALUES ('delete', old.id, old.body);
+                 INSERT INTO notes_fts (rowid, body) VALUES (new.id, new.body);
+             END;",
+        )?;
+        if !has_note_index {
+            conn.execute("INSERT INTO notes_fts (notes_fts) VALUES ('rebuild')", [])?;
+        }
+        Ok(true)
+    }
+}
+
+/// Version 1: the schema as it stood before versioning. Every statement is idempotent because
+/// unversioned databases may already hold any subset of it.
+fn migrate_v1(conn: &Connection) -> Result<()> {
+    let had_links: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'issue_links')",
+        [],
+        |row| row.get(0),
+    )?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS documents (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            repo TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            external_id TEXT NOT NULL,
+            title TEXT NOT NULL,
+            body TEXT,
+            updated_at TEXT NOT NULL,
+            synced_at TEXT NOT NULL,
+            UNIQUE(repo, kind, external_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS issue_meta (
+            document_id INTEGER PRIMARY KEY,
+            number INTEGER NOT NULL,
+            state TEXT,
+            labels TEXT,
+            FOREIGN KEY(document_id) REFERENCES documents(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS notes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            document_id INTEGER NOT NULL,
+            anchor TEXT,
+            body TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            FOREIGN KEY(document_id) REFERENCES documents(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS note_revisions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            note_id INTEGER NOT NULL,
+            body TEXT NOT NULL,
+            written_at TEXT NOT NULL,
+            FOREIGN KEY(note_id) REFERENCES notes(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS issue_revisions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            document_id INTEGER NOT NULL,
+            title TEXT NOT NULL,
+            body TEXT,
+            updated_at TEXT NOT NULL,
+            FOREIGN KEY(document_id) REFERENCES documents(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS labels (
+            document_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            PRIMARY KEY (document_id, name),
+            FOREIGN KEY(document_id) REFERENCES documents(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS note_refs (
+            note_id INTEGER NOT NULL,
+            repo TEXT NOT NULL,
+            number INTEGER NOT NULL,
+            PRIMARY KEY (note_id, repo, number),
+            FOREIGN KEY(note_id) REFERENCES notes(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS issue_links (
+            document_id INTEGER NOT NULL,
+            target INTEGER NOT NULL,
+            PRIMARY KEY (document_id, target),
+            FOREIGN KEY(document_id) REFERENCES documents(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS comments (
+            comment_id INTEGER PRIMARY KEY,
+            document_id INTEGER NOT NULL,
+            author TEXT NOT NULL,
+            body TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY(document_id) REFERENCES documents(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            document_id INTEGER NOT NULL,
+            type TEXT NOT NULL,
+            actor TEXT,
+            created_at TEXT NOT NULL,
+            detail TEXT,
+            FOREIGN KEY(document_id) REFERENCES documents(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS sync_runs (
+            run_id TEXT NOT NULL,
+            repo TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            status TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            PRIMARY KEY (run_id, repo)
+        );
+
+        CREATE TABLE IF NOT EXISTS sync_state (
+            repo TEXT NOT NULL,
+            resource TEXT NOT NULL,
+            cursor TEXT,
+            updated_at TEXT NOT NULL,
+            PRIMARY KEY (repo, resource)
+        );",
+    )?;
+    ensure_column(conn, "documents", "original_body_length", "INTEGER")?;
+    ensure_column(conn, "issue_meta", "assignees", "TEXT")?;
+    ensure_column(conn, "issue_meta", "timeline_updated_at", "TEXT")?;
+    ensure_column(conn, "issue_meta", "state_reason", "TEXT")?;
+    ensure_column(conn, "issue_meta", "author_login", "TEXT")?;
+    ensure_column(conn, "issue_meta", "is_pull_request", "INTEGER")?;
+    ensure_column(conn, "issue_meta", "triage_state", "TEXT")?;
+    ensure_column(conn, "issue_meta", "triage_note_id", "INTEGER")?;
+    ensure_column(conn, "notes", "position", "INTEGER")?;
+    ensure_column(conn, "issue_meta", "created_at", "TEXT")?;
+
+    if !had_links {
+        let issues = {
+            let mut stmt = conn.prepare(
+                "SELECT documents.id, documents.repo, issue_meta.number,
+                        COALESCE(documents.body, '')
+                 FROM documents
+                 JOIN issue_meta ON issue_meta.document_id = documents.id
+                 WHERE documents.kind = 'issue'",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        for (document_id, repo, number, body) in issues {
+            replace_issue_links(conn, document_id, &repo, number as u64, &body)?;
+        }
+    }
+    Ok(())
+}
+
+/// Makes `text` match itself literally inside a `GLOB` pattern.
+fn glob_escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '*' | '?' | '[' => format!("[{c}]"),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// `FROM ... WHERE ...` selecting the issues of `repo` that match `filter`, with its bound values.
+fn issue_filter_sql(repo: &str, filter: &IssueFilter) -> (String, Vec<Value>) {
+    let mut sql = format!(
+        "FROM documents
+         JOIN issue_meta ON issue_meta.document_id = documents.id
+         WHERE documents.repo = ?1 AND documents.kind IN {ISSUE_KINDS_SQL}",
+    );
+    let mut values: Vec<Value> = vec![Value::from(repo.to_string())];
+    if let Some(state) = &filter.state {
+        values.push(Value::from(state.clone()));
+        sql.push_str(&format!(" AND issue_meta.state = ?{}", values.len()));
+    }
+    if let Some(kind) = &filter.kind {
+        values.push(Value::from(kind.clone()));
+        sql.push_str(&format!(" AND documents.kind = ?{}", values.len()));
+    }
+    if let Some(author) = &filter.author {
+        values.push(Value::from(author.clone()));
+        sql.push_str(&format!(
+            " AND issue_meta.author_login = ?{} COLLATE NOCASE",
+            values.len()
+        ));
+    }
+    if let Some(since) = filter.updated_since {
+        values.push(Value::from(since.to_rfc3339()));
+        sql.push_str(&format!(
+            " AND julianday(documents.updated_at) >= julianday(?{})",
+            values.len()
+        ));
+    }
+    if let Some(login) = &filter.involves {
+        values.push(Value::from(login.clone()));
+        let n = values.len();
+        // A mention is `@login` not preceded by a word character (as in an email address)
+        // nor followed by one that could continue the login, so `@bob` skips `@bobby`.
+        values.push(Value::from(format!(
+            "*[^a-z0-9_]@{}[^a-z0-9-]*",
+            glob_escape(&login.to_lowercase())
+        )));
+        let mention = values.len();
+        sql.push_str(&format!(
+            " AND (issue_meta.author_login = ?{n} COLLATE NOCASE
+                   OR ', ' || issue_meta.assignees || ',' LIKE '%, ' || ?{n} || ',%'
+                   OR lower(' ' || COALESCE(documents.body, '') || ' ') GLOB ?{mention})"
+        ));
+    }
+    if let Some(login) = &filter.assignee {
+        values.push(Value::from(login.clone()));
+        sql.push_str(&format!(
+            " AND ', ' || issue_meta.assignees || ',' LIKE '%, ' || ?{} || ',%'",
+            values.len()
+        ));
+    }
+    if let Some(milestone) = &filter.milestone {
+        values.push(Value::from(milestone.clone()));
+        sql.push_str(&format!(
+            " AND issue_meta.milestone = ?{} COLLATE NOCASE",
+            values.len()
+        ));
+    }
+    if filter.no_milestone {
+        sql.push_str(" AND issue_meta.milestone IS NULL");
+    }
+    if let Some(empty) = filter.body_empty {
+        let op = if empty { "=" } else { "<>" };
+        sql.push_str(&format!(" AND {BLANK_BODY_SQL} {op} ''"));
+    }
+    if let Some(min) = filter.min_assignees {
+        values.push(Value::from(min));
+        sql.push_str(&format!(" AND {ASSIGNEE_COUNT_SQL} >= ?{}", values.len()));
+    }
+    if let Some(max) = filter.max_assignees {
+        values.push(Value::from(max));
+        sql.push_str(&format!(" AND {ASSIGNEE_COUNT_SQL} <= ?{}", values.len()));
+    }
+    if let Some(target) = filter.references {
+        values.push(Value::from(target as i64));
+        sql.push_str(&format!(
+            " AND EXISTS (SELECT 1 FROM issue_links
+                          WHERE issue_links.document_id = documents.id
+                            AND issue_links.target = ?{})",
+            values.len()
+        ));
+    }
+    // Caches that never ran `migrate-labels` only have the legacy `", "`-joined string.
+    for label in &filter.labels {
+        values.push(Value::from(label.clone()));
+        let n = values.len();
+        sql.push_str(&format!(
+            " AND (EXISTS (SELECT 1 FROM labels
+                           WHERE labels.document_id = documents.id
+                             AND labels.name = ?{n} COLLATE NOCASE)
+                   OR (NOT EXISTS (SELECT 1 FROM labels
+                                   WHERE labels.document_id = documents.id)
+                       AND instr(lower(', ' || COALESCE(issue_meta.labels, '') || ', '),
+                                 lower(', ' || ?{n} || ', ')) > 0))"
+        ));
+    }
+    if filter.unlabeled {
+        sql.push_str(
+            " AND NOT EXISTS (SELECT 1 FROM labels WHERE labels.document_id = documents.id)
+              AND COALESCE(issue_meta.labels, '') = ''",
+        );
+    }
+    if filter.body_incomplete {
+        sql.push_str(&format!(
+            " AND ({BLANK_BODY_SQL} = '' OR documents.original_body_length IS NOT NULL)"
+        ));
+    }
+    (sql, values)
+}
+
+/// Version 2: pull requests get their own `documents.kind` instead of passing as issues.
+fn migrate_v2(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "UPDATE documents SET kind = 'pull_request'
+         WHERE kind = 'issue'
+           AND id IN (SELECT document_id FROM issue_meta WHERE is_pull_request = 1)",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Version 3: repository names are stored lowercased, matching `Config::normalize_repo`.
+/// Where both casings of a document were cached, the mixed-case copy is merged into the
+/// lowercase one: its notes, comments, revisions and triage state move over before it is
+/// deleted, so nothing is left unreachable.
+fn migrate_v3(conn: &Connection) -> Result<()> {
+    let duplicates = {
+        let mut stmt = conn.prepare(
+            "SELECT old.id, new.id
+             FROM documents AS old
+             JOIN documents AS new
+               ON new.repo = lower(old.repo)
+              AND new.kind = old.kind
+              AND new.external_id = old.external_id
+             WHERE old.repo <> lower(old.repo)",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    let has_fts: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'documents_fts')",
+        [],
+        |row| row.get(0),
+    )?;
+    for (old_id, new_id) in duplicates {
+        for table in ["notes", "comments", "issue_revisions"] {
+            conn.execute(
+                &format!("UPDATE {table} SET document_id = ?2 WHERE document_id = ?1"),
+                [old_id, new_id],
+            )?;
+        }
+        conn.execute(
+            "UPDATE issue_meta SET
+                 triage_state = COALESCE(triage_state,
+                     (SELECT triage_state FROM issue_meta WHERE document_id = ?1)),
+                 triage_note_id = COALESCE(triage_note_id,
+                     (SELECT triage_note_id FROM issue_meta WHERE document_id = ?1))
+             WHERE document_id = ?2",
+            [old_id, new_id],
+        )?;
+        if has_fts {
+            conn.execute("DELETE FROM documents_fts WHERE rowid = ?1", [old_id])?;
+        }
+        conn.execute("DELETE FROM documents WHERE id = ?1", [old_id])?;
+    }
+    // Rows that collide after lowercasing duplicate one that already exists.
+    conn.execute_batch(
+        "UPDATE documents SET repo = lower(repo) WHERE repo <> lower(repo);
+         UPDATE OR IGNORE note_refs SET repo = lower(repo) WHERE repo <> lower(repo);
+         DELETE FROM note_refs WHERE repo <> lower(repo);
+         UPDATE OR IGNORE sync_runs SET repo = lower(repo) WHERE repo <> lower(repo);
+         DELETE FROM sync_runs WHERE repo <> lower(repo);
+         UPDATE OR IGNORE sync_state SET repo = lower(repo) WHERE repo <> lower(repo);
+         DELETE FROM sync_state WHERE repo <> lower(repo);",
+    )?;
+    Ok(())
+}
+
+/// Version 4: `issue_meta.comment_count`, GitHub's comment count as of the last sync. Rows
+/// cached before start at 0 and pick up the real count on their next sync.
+fn migrate_v4(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE issue_meta ADD COLUMN comment_count INTEGER NOT NULL DEFAULT 0;",
+    )?;
+    Ok(())
+}
+
+/// Version 5: `issue_meta.milestone`, the title of the issue's milestone. NULL means no
+/// milestone, or a row not synced since.
+fn migrate_v5(conn: &Connection) -> Result<()> {
+    conn.execute_batch("ALTER TABLE issue_meta ADD COLUMN milestone TEXT;")?;
+    Ok(())
+}
+
+/// Logins assigned to `issue`. Older API responses only fill the single `assignee` field.
+pub fn issue_assignees(issue: &Issue) -> Vec<String> {
+    let mut assignees = issue
+        .assignees
+        .iter()
+        .map(|author| author.login.clone())
+        .collect::<Vec<_>>();
+    if assignees.is_empty()
+        && let Some(assignee) = &issue.assignee
+    {
+        assignees.push(assignee.login.clone());
+    }
+    assignees
+}
+
+/// One issue as it is cached, whether it came from GitHub or from an archive.
+struct IssueRow<'a> {
+    number: i64,
+    is_pull_request: bool,
+    title: &'a str,
+    body: &'a str,
+    /// Length of the body before `max_body_size` truncated it.
+    original_body_length: Option<i64>,
+    updated_at: &'a str,
+    state: Option<&'a str>,
+    state_reason: Option<&'a str>,
+    labels: &'a [String],
+    assignees: &'a [String],
+    author: Option<&'a str>,
+    created_at: Option<&'a str>,
+    comment_count: i64,
+    milestone: Option<&'a str>,
+    /// Replaces the local triage state when set; `None` keeps whatever is there.
+    triage_state: Option<&'a str>,
+}
+
+/// Inserts or updates `row` in `documents`, `issue_meta`, `labels`, `issue_links` and the
+/// full-text index, keeping the previous title and body as a revision when they change.
+/// Returns the document id.
+fn write_issue_row(
+    conn: &Connection,
+    fts: bool,
+    repo: &str,
+    row: &IssueRow,
+    synced_at: &str,
+) -> Result<i64> {
+    let external_id = row.number.to_string();
+    let kind = if row.is_pull_request {
+        "pull_request"
+    } else {
+        "issue"
+    };
+    set_document_kind(conn, repo, &external_id, kind)?;
+
+    conn.execute(
+        &format!(
+            "INSERT INTO issue_revisions (document_id, title, body, updated_at)
+             SELECT id, title, body, updated_at
+             FROM documents
+             WHERE repo = ?1 AND kind IN {ISSUE_KINDS_SQL} AND external_id = ?2
+               AND (title IS NOT ?3 OR body IS NOT ?4)"
+        ),
+        params![repo, &external_id, row.title, row.body],
+    )?;
+
+    conn.execute(
+        "INSERT INTO documents
+             (repo, kind, external_id, title, body, original_body_length, updated_at, synced_at)
+         VALUES (?1, ?8, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT(repo, kind, external_id) DO UPDATE SET
+             title=excluded.title,
+             body=excluded.body,
+             original_body_length=excluded.original_body_length,
+             updated_at=excluded.updated_at,
+             synced_at=excluded.synced_at",
+        params![
+            repo,
+            &external_id,
+            row.title,
+            row.body,
+            row.original_body_length,
+            row.updated_at,
+            synced_at,
+            kind
+        ],
+    )?;
+
+    let document_id: i64 = conn.query_row(
+        &format!(
+            "SELECT id FROM documents WHERE repo=?1 AND kind IN {ISSUE_KINDS_SQL} AND external_id=?2"
+        ),
+        params![repo, &external_id],
+        |row| row.get(0),
+    )?;
+
+    if fts {
+        conn.execute("DELETE FROM documents_fts WHERE rowid = ?1", [document_id])?;
+        conn.execute(
+            "INSERT INTO documents_fts (rowid, title, body) VALUES (?1, ?2, ?3)",
+            params![document_id, row.title, row.body],
+        )?;
+    }
+
+    replace_issue_links(conn, document_id, repo, row.number as u64, row.body)?;
+
+    conn.execute("DELETE FROM labels WHERE document_id = ?1", [document_id])?;
+    for label in row.labels {
+        conn.execute(
+            "INSERT OR IGNORE INTO labels (document_id, name) VALUES (?1, ?2)",
+            params![document_id, label],
+        )?;
+    }
+
+    conn.execute(
+        "INSERT INTO issue_meta
+             (document_id, number, state, state_reason, labels, assignees, author_login,
+              is_pull_request, created_at, comment_count, milestone, triage_state)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+         ON CONFLICT(document_id) DO UPDATE SET
+             number=excluded.number,
+             state=excluded.state,
+             state_reason=excluded.state_reason,
+             labels=excluded.labels,
+             assignees=excluded.assignees,
+             author_login=excluded.author_login,
+             is_pull_request=excluded.is_pull_request,
+             created_at=excluded.created_at,
+             comment_count=excluded.comment_count,
+             milestone=excluded.milestone,
+             triage_state=COALESCE(excluded.triage_state, issue_meta.triage_state)",
+        params![
+            document_id,
+            row.number,
+            row.state,
+            row.state_reason,
+            row.labels.join(", "),
+            row.assignees.join(", "),
+            row.author,
+            row.is_pull_request,
+            row.created_at,
+            row.comment_count,
+            row.milestone,
+            row.triage_state
+        ],
+    )?;
+
+    Ok(document_id)
+}
+
+/// Moves a cached issue or pull request to `kind` if GitHub now reports it as the other one, so
+/// the following upsert updates it instead of adding a second row.
+fn set_document_kind(conn: &Connection, repo: &str, external_id: &str, kind: &str) -> Result<()> {
+    conn.execute(
+        &format!(
+            "UPDATE documents SET kind = ?3
+         WHERE repo = ?1 AND external_id = ?2
+           AND kind IN {ISSUE_KINDS_SQL} AND kind <> ?3"
+        ),
+        params![repo, external_id, kind],
+    )?;
+    Ok(())
+}
+
+/// Rewrites the `issue_links` rows of a document from the references in its body.
+fn replace_issue_links(
+    conn: &Connection,
+    document_id: i64,
+    repo: &str,
+    number: u64,
+    body: &str,
+) -> Result<()> {
+    conn.execute(
+        "DELETE FROM issue_links WHERE document_id = ?1",
+        [document_id],
+    )?;
+    for target in issue_references(repo, body) {
+        if target != number {
+            conn.execute(
+                "INSERT OR IGNORE INTO issue_links (document_id, target) VALUES (?1, ?2)",
+                params![document_id, target as i64],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// `#42`, `owner/name#42` or an issue/PR URL; the repository, when present, is group 1 or 3.
+static ISSUE_REFERENCE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)(?:^|[^\w/#])(?:([\w.-]+/[\w.-]+))?#(\d+)\b|github\.com/([\w.-]+/[\w.-]+)/(?:issues|pull)/(\d+)\b",
+    )
+    .unwrap()
+});
+
+/// Issue numbers of `repo` referenced in `body`, as `#42`, `owner/name#42` or an issue/PR URL.
+fn issue_references(repo: &str, body: &str) -> Vec<u64> {
+    ISSUE_REFERENCE
+        .captures_iter(body)
+        .filter_map(|captures| {
+            let (named, number) = match captures.get(2) {
+                Some(number) => (captures.get(1), number),
+                None => (captures.get(3), captures.get(4)?),
+            };
+            named
+                .is_none_or(|named| named.as_str().eq_ignore_ascii_case(repo))
+                .then(|| number.as_str().parse().ok())?
+        })
+        .collect()
+}
+
+/// Summarizes the event-specific payload worth showing next to a timeline entry.
+fn event_detail(event: &TimelineEvent) -> Option<String> {
+    if let Some(label) = &event.label {
+        return Some(label.name.clone());
+    }
+    if let Some(assignee) = &event.assignee {
+        return Some(assignee.login.clone());
+    }
+    if let Some(milestone) = &event.milestone {
+        return Some(milestone.title.clone());
+    }
+    if let Some(commit) = &event.commit_id {
+        return Some(commit.chars().take(7).collect());
+    }
+    event
+        .body
+        .as_deref()
+        .map(str::trim)
+        .filter(|body| !body.is_empty())
+        .map(str::to_string)
+}
+
+/// Splits a `", "`-joined column such as `issue_meta.labels` back into its entries.
+fn split_list(raw: Option<String>) -> Vec<String> {
+    raw.unwrap_or_default()
+        .split(", ")
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn parse_timestamp(raw: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(raw)
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(|_| Utc::now())
+}
+
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", quote_identifier(table)))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(columns)
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Renders a stored value as an SQL literal, escaping text and hex-encoding blobs.
+fn sql_literal(value: ValueRef<'_>) -> String {
+    match value {
+        ValueRef::Null => "NULL".to_string(),
+        ValueRef::Integer(i) => i.to_string(),
+        ValueRef::Real(f) => format!("{f:?}"),
+        ValueRef::Text(text) => format!("'{}'", String::from_utf8_lossy(text).replace('\'', "''")),
+        ValueRef::Blob(bytes) => {
+            let hex: String = bytes.iter().map(|b| format!("{b:02X}")).collect();
+            format!("X'{hex}'")
+        }
+    }
+}
+
+/// Adds `column` to `table` when an older database predates it.
+fn ensure_column(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(1)?;
+        if name == column {
+            return Ok(());
+        }
+    }
+    conn.execute(
+        &format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"),
+        [],
+    )?;
+    Ok(())
+}
+
+pub fn database_path(data_dir: Option<&Path>) -> Result<PathBuf> {
+    if let Some(dir) = data_dir {
+        return Ok(dir.join(DB_FILE_NAME));
+    }
+    let dirs = directories::ProjectDirs::from("com", "LexicalMathical", "NoteHub")
+        .context("unable to determine data directory")?;
+    Ok(dirs.data_dir().join(DB_FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An in-memory database that has applied only the first `version` migrations.
+    fn database_at(version: usize) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        Storage::apply_pragmas(&conn).unwrap();
+        conn.execute_batch("CREATE TABLE schema_version (version INTEGER NOT NULL);")
+            .unwrap();
+        for step in &MIGRATIONS[..version] {
+            step(&conn).unwrap();
+        }
+        conn.execute(
+            "INSERT INTO schema_version (version) VALUES (?1)",
+            [version as i64],
+        )
+        .unwrap();
+        conn
+    }
+
+    /// Finishes opening `conn` the way `Storage::open` does.
+    fn storage(mut conn: Connection) -> Storage {
+        Storage::migrate(&mut conn).unwrap();
+        let fts = Storage::create_fts(&conn).unwrap();
+        Storage { conn, fts }
+    }
+
+    fn insert_issue(conn: &Connection, repo: &str, number: i64, title: &str) -> i64 {
+        conn.execute(
+            "INSERT INTO documents (repo, kind, external_id, title, body, updated_at, synced_at)
+             VALUES (?1, 'issue', ?2, ?3, '', '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z')",
+            params![repo, number.to_string(), title],
+        )
+        .unwrap();
+        let id = conn.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO issue_meta (document_id, number, state) VALUES (?1, ?2, 'open')",
+            [id, number],
+        )
+        .unwrap();
+        id
+    }
+
+    fn insert_note(conn: &Connection, document_id: i64, body: &str) {
+        conn.execute(
+            "INSERT INTO notes (document_id, body, created_at, updated_at)
+             VALUES (?1, ?2, '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z')",
+            params![document_id, body],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn migrate_upgrades_an_unversioned_database_to_the_current_version() {
+        // The schema as created before `schema_version` existed.
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE documents (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                repo TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                external_id TEXT NOT NULL,
+                title TEXT NOT NULL,
+                body TEXT,
+                updated_at TEXT NOT NULL,
+                synced_at TEXT NOT NULL,
+                UNIQUE(repo, kind, external_id)
+            );
+            CREATE TABLE issue_meta (
+                document_id INTEGER PRIMARY KEY,
+                number INTEGER NOT NULL,
+                state TEXT,
+                labels TEXT,
+                FOREIGN KEY(document_id) REFERENCES documents(id) ON DELETE CASCADE
+            );
+            CREATE TABLE notes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                document_id INTEGER NOT NULL,
+                anchor TEXT,
+                body TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                FOREIGN KEY(document_id) REFERENCES documents(id) ON DELETE CASCADE
+            );
+            CREATE TABLE sync_state (
+                repo TEXT NOT NULL,
+                resource TEXT NOT NULL,
+                cursor TEXT,
+                updated_at TEXT NOT NULL,
+                PRIMARY KEY (repo, resource)
+            );",
+        )
+        .unwrap();
+        insert_issue(&conn, "owner/repo", 1, "cached before versioning");
+
+        Storage::migrate(&mut conn).unwrap();
+        let storage = Storage { conn, fts: false };
+        assert_eq!(storage.schema_version().unwrap(), SCHEMA_VERSION);
+        assert!(storage.get_issue("owner/repo", 1).unwrap().is_some());
+    }
+
+    #[test]
+    fn lowercasing_repos_merges_both_casings_into_one_document() {
+        let conn = database_at(2);
+        let mixed = insert_issue(&conn, "Owner/Repo", 7, "old casing");
+        let lower = insert_issue(&conn, "owner/repo", 7, "new casing");
+        insert_note(&conn, mixed, "written under Owner/Repo");
+        insert_note(&conn, lower, "written under owner/repo");
+        conn.execute(
+            "INSERT INTO comments (comment_id, document_id, author, body, created_at)
+             VALUES (1, ?1, 'octocat', 'hi', '2024-01-01T00:00:00Z')",
+            [mixed],
+        )
+        .unwrap();
+
+        let storage = storage(conn);
+        let documents: Vec<(i64, String)> = storage
+            .conn
+            .prepare("SELECT id, repo FROM documents")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(documents, vec![(lower, "owner/repo".to_string())]);
+        let count = |table: &str| -> i64 {
+            storage
+                .conn
+                .query_row(
+                    &format!("SELECT COUNT(*) FROM {table} WHERE document_id = ?1"),
+                    [lower],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+        assert_eq!(count("notes"), 2);
+        assert_eq!(count("comments"), 1);
+        assert!(storage.get_issue("owner/repo", 7).unwrap().is_some());
+    }
+
+    #[test]
+    fn open_with_a_data_dir_creates_the_database_there() {
+        let dir = std::env::temp_dir().join(format!("notehub-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        Storage::open(Some(&dir)).unwrap();
+        let path = database_path(Some(&dir)).unwrap();
+        assert_eq!(path, dir.join(DB_FILE_NAME));
+        assert!(path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn involves_matches_whole_mentions_only() {
+        let storage = Storage::open_in_memory().unwrap();
+        for (number, body) in [
+            (1, "cc @bob"),
+            (2, "thanks @Bob."),
+            (3, "cc @bobby"),
+            (4, "mail alice@bob.com"),
+            (5, "@bob-team please look"),
+        ] {
+            let id = insert_issue(&storage.conn, "o/n", number, "t");
+            storage
+                .conn
+                .execute(
+                    "UPDATE documents SET body = ?2 WHERE id = ?1",
+                    params![id, body],
+                )
+                .unwrap();
+        }
+        let filter = IssueFilter {
+            involves: Some("bob".to_string()),
+            ..IssueFilter::default()
+        };
+        let mut numbers = storage
+            .list_issues("o/n", &filter)
+            .unwrap()
+            .into_iter()
+            .map(|issue| issue.number)
+            .collect::<Vec<_>>();
+        numbers.sort();
+        assert_eq!(numbers, vec![1, 2]);
+    }
+
+    #[test]
+    fn archives_round_trip_comment_counts_and_milestones() {
+        let source = Storage::open_in_memory().unwrap();
+        let id = insert_issue(&source.conn, "o/n", 3, "t");
+        source
+            .conn
+            .execute(
+                "UPDATE issue_meta SET comment_count = 4, milestone = 'v1' WHERE document_id = ?1",
+                [id],
+            )
+            .unwrap();
+        let mut issues = Vec::new();
+        source
+            .for_each_archived_issue("o/n", |issue| {
+                issues.push(issue);
+                Ok(())
+            })
+            .unwrap();
+        let archive = Archive {
+            schema_version: SCHEMA_VERSION,
+            repos: vec![ArchivedRepo {
+                repo: "o/n".to_string(),
+                issues,
+            }],
+        };
+
+        let mut target = Storage::open_in_memory().unwrap();
+        target.import_archive(&archive).unwrap();
+        let (comments, milestone): (i64, Option<String>) = target
+            .conn
+            .query_row(
+                "SELECT comment_count, milestone FROM issue_meta WHERE number = 3",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((comments, milestone.as_deref()), (4, Some("v1")));
+    }
+
+    #[test]
+    fn import_keeps_cached_issues_newer_than_the_archive() {
+        let mut storage = Storage::open_in_memory().unwrap();
+        let id = insert_issue(&storage.conn, "o/n", 1, "renamed since the export");
+        storage
+            .conn
+            .execute(
+                "UPDATE documents SET updated_at = '2024-03-01T00:00:00Z' WHERE id = ?1",
+                [id],
+            )
+            .unwrap();
+        let archive: Archive = serde_json::from_value(serde_json::json!({
+            "schema_version": SCHEMA_VERSION,
+            "repos": [{
+                "repo": "o/n",
+                "issues": [{
+                    "number": 1,
+                    "title": "as exported",
+                    "body": null,
+                    "state": "open",
+                    "state_reason": null,
+                    "author": null,
+                    "created_at": null,
+                    "updated_at": "2024-02-01T00:00:00Z",
+                    "is_pull_request": false,
+                    "triage_state": null,
+                    "notes": [{
+                        "id": 9,
+                        "anchor": null,
+                        "body": "still imported",
+                        "created_at": "2024-02-01T00:00:00Z",
+                        "updated_at": "2024-02-01T00:00:00Z"
+                    }]
+                }]
+            }]
+        }))
+        .unwrap();
+
+        let summaries = storage.import_archive(&archive).unwrap();
+        assert_eq!(
+            (
+                summaries[0].issues,
+                summaries[0].stale_issues,
+                summaries[0].notes
+            ),
+            (0, 1, 1)
+        );
+        let issue = storage.get_issue("o/n", 1).unwrap().unwrap();
+        assert_eq!(issue.title, "renamed since the export");
+    }
+
+    #[test]
+    fn reattaching_a_note_drops_comment_anchors_only() {
+        let storage = Storage::open_in_memory().unwrap();
+        insert_issue(&storage.conn, "o/n", 1, "wrong issue");
+        let to = insert_issue(&storage.conn, "o/n", 2, "right issue");
+        let comment = storage
+            .add_note("o/n", 1, "see comment", Some("comment:42"))
+            .unwrap();
+        let file = storage
+            .add_note("o/n", 1, "see file", Some("file:src/lib.rs#L3"))
+            .unwrap();
+
+        assert!(storage.reattach_note(comment, to).unwrap());
+        assert!(storage.reattach_note(file, to).unwrap());
+        assert!(!storage.reattach_note(999, to).unwrap());
+
+        let anchor = |id| storage.note_anchor(id).unwrap().unwrap();
+        assert_eq!((anchor(comment).number, anchor(comment).anchor), (2, None));
+        assert_eq!(anchor(file).anchor.as_deref(), Some("file:src/lib.rs#L3"));
+        assert!(storage.list_notes("o/n", 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn unlabeled_falls_back_to_the_legacy_label_string() {
+        let storage = Storage::open_in_memory().unwrap();
+        insert_issue(&storage.conn, "o/n", 1, "no labels");
+        let legacy = insert_issue(&storage.conn, "o/n", 2, "legacy labels only");
+        storage
+            .conn
+            .execute(
+                "UPDATE issue_meta SET labels = 'bug, ui' WHERE document_id = ?1",
+                [legacy],
+            )
+            .unwrap();
+        let migrated = insert_issue(&storage.conn, "o/n", 3, "labels table");
+        storage
+            .conn
+            .execute(
+                "INSERT INTO labels (document_id, name) VALUES (?1, 'bug')",
+                [migrated],
+            )
+            .unwrap();
+
+        let filter = IssueFilter {
+            unlabeled: true,
+            ..IssueFilter::default()
+        };
+        let numbers = storage
+            .list_issues("o/n", &filter)
+            .unwrap()
+            .into_iter()
+            .map(|issue| issue.number)
+            .collect::<Vec<_>>();
+        assert_eq!(numbers, vec![1]);
+    }
+
+    #[test]
+    fn issue_references_keep_only_this_repository() {
+        let body = "Fixes #1, see O/N#2 and other/repo#3.
+            https://github.com/o/n/issues/4 https://github.com/o/n/pull/5
+            https://github.com/x/y/issues/6 mail@host#7 x/o/n#8 ##9";
+        assert_eq!(issue_references("o/n", body), vec![1, 2, 4, 5]);
+    }
+
+    #[test]
+    fn label_filter_falls_back_to_the_legacy_label_string() {
+        let storage = Storage::open_in_memory().unwrap();
+        for (number, labels) in [(1, "bug, ui"), (2, "Bug"), (3, "bugfix, needs info, maybe")] {
+            let id = insert_issue(&storage.conn, "o/n", number, "t");
+            storage
+                .conn
+                .execute(
+                    "UPDATE issue_meta SET labels = ?2 WHERE document_id = ?1",
+                    params![id, labels],
+                )
+                .unwrap();
+        }
+        let numbers = |labels: &[&str]| {
+            let filter = IssueFilter {
+                labels: labels.iter().map(|label| label.to_string()).collect(),
+                ..IssueFilter::default()
+            };
+            let mut numbers = storage
+                .list_issues("o/n", &filter)
+                .unwrap()
+                .into_iter()
+                .map(|issue| issue.number)
+                .collect::<Vec<_>>();
+            numbers.sort();
+            numbers
+        };
+        assert_eq!(numbers(&["bug"]), vec![1, 2]);
+        assert_eq!(numbers(&["bug", "ui"]), vec![1]);
+        assert_eq!(numbers(&["needs info"]), vec![3]);
+    }
+
+    #[test]
+    fn repo_sql_export_scopes_note_refs_by_the_note() {
+        let storage = Storage::open_in_memory().unwrap();
+        insert_issue(&storage.conn, "a/b", 1, "here");
+        insert_issue(&storage.conn, "c/d", 2, "elsewhere");
+        let own = storage.add_note("a/b", 1, "own note", None).unwrap();
+        let other = storage.add_note("c/d", 2, "other note", None).unwrap();
+        storage.add_note_ref(own, "c/d", 2).unwrap();
+        storage.add_note_ref(other, "a/b", 1).unwrap();
+
+        let mut out = Vec::new();
+        storage.export_sql(&mut out, Some("a/b")).unwrap();
+        let sql = String::from_utf8(out).unwrap();
+        let refs = sql
+            .lines()
+            .filter(|line| line.starts_with("INSERT INTO \"note_refs\""))
+            .collect::<Vec<_>>();
+        assert_eq!(refs.len(), 1, "{refs:?}");
+        assert!(
+            refs[0].ends_with(&format!("VALUES ({own}, 'c/d', 2);")),
+            "{}",
+            refs[0]
+        );
+    }
+
+    #[test]
+    fn split_list_splits_where_the_list_was_joined() {
+        assert_eq!(
+            split_list(Some("bug, needs info,maybe, ui".to_string())),
+            vec!["bug", "needs info,maybe", "ui"]
+        );
+        assert!(split_list(Some(String::new())).is_empty());
+        assert!(split_list(None).is_empty());
+    }
+
+    #[test]
+    fn rename_repo_drops_refs_that_would_duplicate() {
+        let storage = Storage::open_in_memory().unwrap();
+        insert_issue(&storage.conn, "o/old", 1, "t");
+        insert_issue(&storage.conn, "x/y", 1, "t");
+        let note = storage.add_note("x/y", 1, "both names", None).unwrap();
+        storage.add_note_ref(note, "o/old", 3).unwrap();
+        storage.add_note_ref(note, "o/new", 3).unwrap();
+        storage.add_note_ref(note, "o/old", 4).unwrap();
+
+        assert_eq!(storage.rename_repo("o/old", "o/new").unwrap(), 1);
+
+        let mut stmt = storage
+            .conn
+            .prepare("SELECT repo, number FROM note_refs ORDER BY number")
+            .unwrap();
+        let refs = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            refs,
+            vec![("o/new".to_string(), 3), ("o/new".to_string(), 4)]
+        );
+    }
 }